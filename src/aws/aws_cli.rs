@@ -1,47 +1,81 @@
-use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 use crate::{
-    aws::aws_pr::AwsPr, constants, custom_error::CustomResult, logger::Logger,
-    zsh_command::ZshCommand,
+    aws::{
+        aws_codeartifact::CodeArtifactState,
+        aws_credentials_file::CredentialsFile,
+        aws_pr::{
+            ApprovalState, AwsPr, CommentLocation, CreatePrOptions, MergeCommit, MergeStrategy,
+            PrStatus,
+        },
+        aws_sts::AssumeRoleOptions,
+    },
+    command::{self, CommandLine, CommandRunner},
+    constants,
+    custom_error::{CustomError, CustomResult},
+    logger::Logger,
 };
 
 use super::aws_sso::AwsSso;
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Commit {
-    pull_request: PullRequest,
-}
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct PullRequest {
-    pull_request_id: String,
-}
-
 pub struct AwsCli {
     logger: Logger,
-    zsh_command: ZshCommand,
+    runner: Arc<dyn CommandRunner>,
 }
 
 impl AwsCli {
     pub fn new() -> Self {
+        Self::with_runner(command::default_runner())
+    }
+
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
         Self {
             logger: Logger::new(),
-            zsh_command: ZshCommand::new(),
+            runner,
         }
     }
 
-    pub fn login(&self) -> CustomResult<()> {
+    pub fn login(
+        &self,
+        write_credentials: Option<&str>,
+        force: bool,
+        assume_role: Option<AssumeRoleOptions>,
+    ) -> CustomResult<()> {
         self.logger.info("Logging in to AWS");
-        AwsSso::new(constants::DEV_ROLE.to_string()).login()?;
+        let role_arn = assume_role.as_ref().map(|options| options.role_arn.clone());
+        let sso = AwsSso::new(constants::DEV_ROLE.to_string())
+            .with_runner(Arc::clone(&self.runner))
+            .with_assume_role(assume_role);
+        sso.login()?;
         self.logger.info("Logged in to AWS");
 
+        if let Some(profile) = write_credentials {
+            self.logger.info(format!(
+                "Writing role credentials to credentials profile '{}'",
+                profile
+            ));
+            let creds = sso.get_sso_credentials()?;
+            CredentialsFile::new()?.write_profile(profile, &creds, force)?;
+            self.logger
+                .info(format!("Wrote credentials profile '{}'", profile));
+        } else if let Some(role_arn) = role_arn {
+            // Nothing keeps the credentials, but assuming the role checks it can be
+            let creds = sso.get_sso_credentials()?;
+            self.logger.info(format!(
+                "Assumed role '{}' until {}, pass --write-credentials to keep the credentials",
+                role_arn,
+                creds.expires_at()?.to_rfc3339()
+            ));
+        }
+
         Ok(())
     }
 
     fn change_role(&self, role: &str) -> CustomResult<()> {
         self.logger.info(format!("Changing AWS role to '{}'", role));
-        AwsSso::new(role.to_string()).set_sso_credentials()?;
+        AwsSso::new(role.to_string())
+            .with_runner(Arc::clone(&self.runner))
+            .set_sso_credentials()?;
         self.logger.info(format!("Changed AWS role to '{}'", role));
 
         Ok(())
@@ -50,16 +84,19 @@ impl AwsCli {
     pub async fn create_pull_request(
         &self,
         repo: &str,
-        title: Option<&str>,
-        source_branch: Option<&str>,
-        target: &str,
-        abort_early: bool,
+        options: &CreatePrOptions,
     ) -> CustomResult<String> {
         self.logger
             .info(format!("Creating pull request in AWS: {}", repo));
-        self.change_role(constants::DEV_ROLE)?;
-        let pr_link = AwsPr::new()
-            .create(repo, title, source_branch, target, abort_early)
+        // SSO may wait for a browser login, keep it off the runtime threads
+        let runner = Arc::clone(&self.runner);
+        tokio::task::spawn_blocking(move || {
+            AwsCli::with_runner(runner).change_role(constants::DEV_ROLE)
+        })
+        .await
+        .map_err(|err| CustomError::CommandExecution(format!("Task failed: {}", err)))??;
+        let pr_link = AwsPr::with_runner(Arc::clone(&self.runner))
+            .create(repo, options)
             .await?;
 
         self.logger
@@ -69,10 +106,80 @@ impl AwsCli {
         Ok(pr_link)
     }
 
+    pub fn list_pull_requests(&self, repo: &str, status: PrStatus, mine: bool) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        AwsPr::with_runner(Arc::clone(&self.runner)).list(repo, status, mine)
+    }
+
+    pub fn show_pull_request(&self, repo: &str, id: &str) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        AwsPr::with_runner(Arc::clone(&self.runner)).show(repo, id)
+    }
+
+    pub fn merge_pull_request(
+        &self,
+        repo: &str,
+        id: &str,
+        strategy: MergeStrategy,
+        commit: &MergeCommit,
+        delete_branch: bool,
+    ) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        AwsPr::with_runner(Arc::clone(&self.runner)).merge(
+            repo,
+            id,
+            strategy,
+            commit,
+            delete_branch,
+        )
+    }
+
+    pub fn review_pull_request(
+        &self,
+        repo: &str,
+        id: Option<&str>,
+        state: ApprovalState,
+    ) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        let aws_pr = AwsPr::with_runner(Arc::clone(&self.runner));
+        let id = aws_pr.resolve_id(repo, id)?;
+        aws_pr.review(&id, state)
+    }
+
+    pub fn comment_pull_request(
+        &self,
+        repo: &str,
+        id: Option<&str>,
+        content: &str,
+        location: Option<&CommentLocation>,
+    ) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        let aws_pr = AwsPr::with_runner(Arc::clone(&self.runner));
+        let id = aws_pr.resolve_id(repo, id)?;
+        aws_pr.comment(repo, &id, content, location)
+    }
+
+    pub fn edit_pull_request(
+        &self,
+        id: &str,
+        title: Option<&str>,
+        description: Option<&str>,
+        edit: bool,
+    ) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        AwsPr::with_runner(Arc::clone(&self.runner)).edit_interactive(id, title, description, edit)
+    }
+
+    pub fn close_pull_request(&self, id: &str) -> CustomResult<()> {
+        self.change_role(constants::DEV_ROLE)?;
+        AwsPr::with_runner(Arc::clone(&self.runner)).close(id)
+    }
+
     pub fn login_npm(&self) -> CustomResult<()> {
         self.logger.info("Logging in to NPM");
-        let command = format!("aws codeartifact login --tool npm --repository conform5-npm-common --domain conform --domain-owner {} --region us-east-1 --profile {}", constants::DOMAIN_OWNER, constants::DEV_ROLE);
-        self.zsh_command.execute(&command)?;
+        let command = Self::codeartifact_login("npm", "conform5-npm-common", "conform");
+        let output = self.runner.execute(&command)?;
+        self.record_codeartifact_login("npm", &output.stdout);
 
         self.logger.info("Logged in to NPM");
 
@@ -81,11 +188,41 @@ impl AwsCli {
 
     pub fn login_pip(&self) -> CustomResult<()> {
         self.logger.info("Logging in to NPM");
-        let command = format!("aws codeartifact login --tool pip --repository conform5-python-common --domain conform5-python --domain-owner {} --region us-east-1 --profile {}", constants::DOMAIN_OWNER, constants::DEV_ROLE);
-        self.zsh_command.execute(&command)?;
+        let command = Self::codeartifact_login("pip", "conform5-python-common", "conform5-python");
+        let output = self.runner.execute(&command)?;
+        self.record_codeartifact_login("pip", &output.stdout);
 
         self.logger.info("Logged in to NPM");
 
         Ok(())
     }
+
+    fn codeartifact_login(tool: &str, repository: &str, domain: &str) -> CommandLine {
+        CommandLine::new("aws").args([
+            "codeartifact",
+            "login",
+            "--tool",
+            tool,
+            "--repository",
+            repository,
+            "--domain",
+            domain,
+            "--domain-owner",
+            constants::DOMAIN_OWNER,
+            "--region",
+            "us-east-1",
+            "--profile",
+            constants::DEV_ROLE,
+        ])
+    }
+
+    fn record_codeartifact_login(&self, tool: &str, stdout: &[u8]) {
+        let result = CodeArtifactState::new()
+            .and_then(|state| state.record_login(tool, &String::from_utf8_lossy(stdout)));
+
+        if let Err(err) = result {
+            self.logger
+                .warn(format!("Failed to record CodeArtifact login: {}", err));
+        }
+    }
 }
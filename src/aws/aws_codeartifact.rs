@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+};
+
+const STATE_FILE: &str = "rust-aws-wrapper/codeartifact.json";
+
+/// Lifetime of a CodeArtifact token when the CLI output can't be parsed.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::hours(12);
+
+/// Remembers when the CodeArtifact tokens configured for npm/pip expire.
+pub struct CodeArtifactState {
+    logger: Logger,
+    path: PathBuf,
+}
+
+impl CodeArtifactState {
+    pub fn new() -> CustomResult<Self> {
+        let path = dirs::cache_dir()
+            .ok_or_else(|| {
+                CustomError::CommandExecution("Failed to get cache directory".to_string())
+            })?
+            .join(STATE_FILE);
+
+        Ok(Self {
+            logger: Logger::new(),
+            path,
+        })
+    }
+
+    /// Records the expiration reported by `aws codeartifact login`, e.g.
+    /// `Login expires in 12 hours at 2024-05-01 22:04:31+02:00`.
+    pub fn record_login(&self, tool: &str, login_output: &str) -> CustomResult<()> {
+        let expires_at = Self::parse_expiration(login_output).unwrap_or_else(|| {
+            self.logger
+                .debug("Could not parse CodeArtifact expiration, assuming default lifetime");
+            Utc::now() + DEFAULT_TOKEN_LIFETIME
+        });
+
+        let mut entries = self.entries();
+        entries.insert(tool.to_string(), expires_at);
+        self.write_entries(&entries)
+    }
+
+    /// Forgets the recorded login of `tool`.
+    pub fn remove(&self, tool: &str) -> CustomResult<()> {
+        let mut entries = self.entries();
+        if entries.remove(tool).is_none() {
+            return Ok(());
+        }
+        self.write_entries(&entries)
+    }
+
+    fn write_entries(&self, entries: &BTreeMap<String, DateTime<Utc>>) -> CustomResult<()> {
+        let json = serde_json::to_string_pretty(entries).map_err(|err| {
+            CustomError::CommandExecution(format!(
+                "Failed to serialize CodeArtifact state: {}",
+                err
+            ))
+        })?;
+        file_utils::write_private_atomic(&self.path, &json)
+    }
+
+    /// Expiration of the last CodeArtifact login per tool.
+    pub fn entries(&self) -> BTreeMap<String, DateTime<Utc>> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn parse_expiration(output: &str) -> Option<DateTime<Utc>> {
+        let line = output
+            .lines()
+            .find(|line| line.contains("Login expires in"))?;
+        let (_, timestamp) = line.rsplit_once(" at ")?;
+
+        DateTime::parse_from_str(timestamp.trim(), "%Y-%m-%d %H:%M:%S%:z")
+            .ok()
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+}
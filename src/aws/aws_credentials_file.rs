@@ -0,0 +1,405 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    aws::aws_sso::RoleCredentials,
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+};
+
+/// Key recording when the credentials written by this tool expire. Its
+/// presence also marks a section as managed by this tool.
+const EXPIRATION_KEY: &str = "raw_expiration";
+/// Keys this tool writes; any other key of a section is left alone.
+const MANAGED_KEYS: &[&str] = &[
+    "aws_access_key_id",
+    "aws_secret_access_key",
+    "aws_session_token",
+    EXPIRATION_KEY,
+];
+
+/// A `[section]` of the credentials file together with the raw lines that
+/// follow its header. Comments and blank lines right above the header belong
+/// to it as well, so they stay in place when the section before is removed.
+/// The leading section has no name and holds everything before the first
+/// header.
+#[derive(Debug)]
+struct Section {
+    name: Option<String>,
+    preamble: Vec<String>,
+    lines: Vec<String>,
+}
+
+impl Section {
+    fn key(line: &str) -> Option<&str> {
+        if CredentialsFile::is_comment(line) {
+            return None;
+        }
+        line.split_once('=').map(|(key, _)| key.trim())
+    }
+
+    fn is_managed(line: &str) -> bool {
+        Self::key(line).is_some_and(|key| MANAGED_KEYS.contains(&key))
+    }
+
+    /// Drops the managed keys and returns whether anything but blank lines
+    /// is left, e.g. `region` or a comment.
+    fn strip_managed(&mut self) -> bool {
+        self.lines.retain(|line| !Self::is_managed(line));
+        self.lines.iter().any(|line| !line.trim().is_empty())
+    }
+
+    fn expiration(&self) -> Option<DateTime<Utc>> {
+        self.lines.iter().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            if key.trim() != EXPIRATION_KEY {
+                return None;
+            }
+            DateTime::parse_from_rfc3339(value.trim())
+                .ok()
+                .map(|dt| dt.with_timezone(&Utc))
+        })
+    }
+}
+
+pub struct CredentialsFile {
+    logger: Logger,
+    path: PathBuf,
+}
+
+impl CredentialsFile {
+    /// Uses `AWS_SHARED_CREDENTIALS_FILE` when set, like the AWS CLI does,
+    /// and `~/.aws/credentials` otherwise.
+    pub fn new() -> CustomResult<Self> {
+        let path = match std::env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => dirs::home_dir()
+                .ok_or_else(|| {
+                    CustomError::CommandExecution("Failed to get home directory".to_string())
+                })?
+                .join(".aws/credentials"),
+        };
+
+        Ok(Self {
+            logger: Logger::new(),
+            path,
+        })
+    }
+
+    /// Writes `creds` into the `[profile]` section, replacing its credential
+    /// keys while keeping other keys, sections and comments untouched.
+    /// Credentials written earlier by this tool that have expired are removed
+    /// like `remove_managed_profiles` does. A section this tool didn't write, e.g. one holding
+    /// long-term keys, is only overwritten with `force`.
+    pub fn write_profile(
+        &self,
+        profile: &str,
+        creds: &RoleCredentials,
+        force: bool,
+    ) -> CustomResult<()> {
+        self.logger.debug(format!(
+            "Writing credentials for '{}' to {}",
+            profile,
+            self.path.display()
+        ));
+
+        let mut sections = self.read_sections()?;
+        self.remove_expired(&mut sections, profile);
+
+        let keys = vec![
+            format!("aws_access_key_id = {}", creds.access_key_id),
+            format!("aws_secret_access_key = {}", creds.secret_access_key),
+            format!("aws_session_token = {}", creds.session_token),
+            format!("{} = {}", EXPIRATION_KEY, creds.expires_at()?.to_rfc3339()),
+        ];
+
+        match sections
+            .iter_mut()
+            .find(|section| section.name.as_deref() == Some(profile))
+        {
+            Some(section) => {
+                if section.expiration().is_none() && !force {
+                    return Err(CustomError::Config(format!(
+                        "Profile '{}' in {} wasn't written by this tool, pass --force to overwrite its credentials",
+                        profile,
+                        self.path.display()
+                    )));
+                }
+
+                let others: Vec<String> = section
+                    .lines
+                    .drain(..)
+                    .filter(|line| !Section::is_managed(line))
+                    .collect();
+                section.lines = keys;
+                section.lines.extend(others);
+            }
+            None => {
+                let separated = sections
+                    .last()
+                    .and_then(|last| last.lines.last().or(last.preamble.last()))
+                    .is_none_or(|line| line.trim().is_empty());
+                sections.push(Section {
+                    name: Some(profile.to_string()),
+                    preamble: if separated {
+                        Vec::new()
+                    } else {
+                        vec![String::new()]
+                    },
+                    lines: keys,
+                });
+            }
+        }
+
+        file_utils::write_private_atomic(&self.path, &Self::render(&sections))?;
+
+        self.logger.debug(format!(
+            "Credentials for '{}' written to {}",
+            profile,
+            self.path.display()
+        ));
+
+        Ok(())
+    }
+
+    /// Whether the file has a `[profile]` section, e.g. with IAM user keys.
+    pub fn has_profile(&self, profile: &str) -> CustomResult<bool> {
+        Ok(self
+            .read_sections()?
+            .iter()
+            .any(|section| section.name.as_deref() == Some(profile)))
+    }
+
+    /// Removes the credentials written by this tool and returns the names of
+    /// their profiles. Sections with other keys, like `region`, keep those.
+    pub fn remove_managed_profiles(&self) -> CustomResult<Vec<String>> {
+        let mut sections = self.read_sections()?;
+        let removed = Self::remove_managed(&mut sections, |_| true);
+
+        if !removed.is_empty() {
+            file_utils::write_private_atomic(&self.path, &Self::render(&sections))?;
+        }
+
+        Ok(removed)
+    }
+
+    fn remove_expired(&self, sections: &mut Vec<Section>, keep: &str) {
+        let now = Utc::now();
+        let removed = Self::remove_managed(sections, |section| {
+            section.name.as_deref() != Some(keep)
+                && section
+                    .expiration()
+                    .is_some_and(|expires_at| expires_at <= now)
+        });
+
+        for profile in removed {
+            self.logger.info(format!(
+                "Removing expired credentials profile '{}'",
+                profile
+            ));
+        }
+    }
+
+    /// Strips the managed keys from the managed sections matching `predicate`
+    /// and returns their names. A section is only dropped when nothing else
+    /// is left in it; the comments above its header stay either way.
+    fn remove_managed<P>(sections: &mut Vec<Section>, predicate: P) -> Vec<String>
+    where
+        P: Fn(&Section) -> bool,
+    {
+        let mut removed = Vec::new();
+        let mut kept = Vec::with_capacity(sections.len());
+
+        for mut section in sections.drain(..) {
+            if section.expiration().is_none() || !predicate(&section) {
+                kept.push(section);
+                continue;
+            }
+
+            removed.extend(section.name.clone());
+            if section.strip_managed() {
+                kept.push(section);
+            } else if !section.preamble.is_empty() {
+                kept.push(Section {
+                    name: None,
+                    preamble: section.preamble,
+                    lines: Vec::new(),
+                });
+            }
+        }
+
+        *sections = kept;
+        removed
+    }
+
+    fn read_sections(&self) -> CustomResult<Vec<Section>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(CustomError::CommandExecution(format!(
+                    "Failed to read {}: {}",
+                    self.path.display(),
+                    err
+                )))
+            }
+        };
+
+        let mut sections = vec![Section {
+            name: None,
+            preamble: Vec::new(),
+            lines: Vec::new(),
+        }];
+
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                // Trailing comments and blank lines introduce the next header
+                let previous = sections.last_mut().map(|section| &mut section.lines);
+                let preamble = match previous {
+                    Some(lines) => {
+                        let start = lines
+                            .iter()
+                            .rposition(|line| !line.trim().is_empty() && !Self::is_comment(line))
+                            .map_or(0, |index| index + 1);
+                        lines.split_off(start)
+                    }
+                    None => Vec::new(),
+                };
+                sections.push(Section {
+                    name: Some(trimmed[1..trimmed.len() - 1].trim().to_string()),
+                    preamble,
+                    lines: Vec::new(),
+                });
+            } else if let Some(section) = sections.last_mut() {
+                section.lines.push(line.to_string());
+            }
+        }
+
+        Ok(sections)
+    }
+
+    fn render(sections: &[Section]) -> String {
+        let mut out = String::new();
+        for section in sections {
+            for line in &section.preamble {
+                out.push_str(line);
+                out.push('\n');
+            }
+            if let Some(name) = &section.name {
+                out.push_str(&format!("[{}]\n", name));
+            }
+            for line in &section.lines {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    fn is_comment(line: &str) -> bool {
+        let trimmed = line.trim_start();
+        trimmed.starts_with('#') || trimmed.starts_with(';')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials_file(name: &str, contents: Option<&str>) -> CredentialsFile {
+        let path = std::env::temp_dir()
+            .join(format!("raw-credentials-test-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_file(&path);
+        if let Some(contents) = contents {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+
+        CredentialsFile {
+            logger: Logger::new(),
+            path,
+        }
+    }
+
+    fn creds() -> RoleCredentials {
+        RoleCredentials {
+            access_key_id: "AKIANEW".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: "session".to_string(),
+            expiration: 4_102_444_800_000,
+        }
+    }
+
+    fn contents(file: &CredentialsFile) -> String {
+        fs::read_to_string(&file.path).unwrap()
+    }
+
+    #[test]
+    fn keeps_comment_above_following_section() {
+        let file = credentials_file(
+            "comment",
+            Some(
+                "[dev]\naws_access_key_id = AKIAOLD\nraw_expiration = 2030-01-01T00:00:00+00:00\n\n# long-term keys\n[prod]\naws_access_key_id = AKIAPROD\n",
+            ),
+        );
+
+        file.write_profile("dev", &creds(), false).unwrap();
+
+        let written = contents(&file);
+        assert!(written.contains("\n\n# long-term keys\n[prod]\naws_access_key_id = AKIAPROD\n"));
+        assert!(written.starts_with("[dev]\naws_access_key_id = AKIANEW\n"));
+        assert!(!written.contains("AKIAOLD"));
+    }
+
+    #[test]
+    fn strips_only_credentials_of_expired_section() {
+        let file = credentials_file(
+            "expired",
+            Some(
+                "[old]\nregion = eu-west-1\naws_access_key_id = AKIAOLD\naws_secret_access_key = old\naws_session_token = old\nraw_expiration = 2020-01-01T00:00:00+00:00\n# next profile\n[gone]\naws_access_key_id = AKIAGONE\nraw_expiration = 2020-01-01T00:00:00+00:00\n",
+            ),
+        );
+
+        file.write_profile("dev", &creds(), false).unwrap();
+
+        let written = contents(&file);
+        assert!(written.starts_with("[old]\nregion = eu-west-1\n# next profile\n\n[dev]\n"));
+        assert!(!written.contains("AKIAOLD"));
+        assert!(!written.contains("[gone]"));
+    }
+
+    #[test]
+    fn refuses_unmanaged_section_without_force() {
+        let original = "[default]\naws_access_key_id = AKIALONG\nregion = eu-west-1\n";
+        let file = credentials_file("unmanaged", Some(original));
+
+        let result = file.write_profile("default", &creds(), false);
+
+        assert!(matches!(result, Err(CustomError::Config(_))));
+        assert_eq!(contents(&file), original);
+
+        file.write_profile("default", &creds(), true).unwrap();
+
+        let written = contents(&file);
+        assert!(written.contains("aws_access_key_id = AKIANEW\n"));
+        assert!(written.contains("region = eu-west-1\n"));
+        assert!(!written.contains("AKIALONG"));
+    }
+
+    #[test]
+    fn creates_missing_file() {
+        let file = credentials_file("missing", None);
+
+        file.write_profile("dev", &creds(), false).unwrap();
+
+        assert_eq!(
+            contents(&file),
+            "[dev]\naws_access_key_id = AKIANEW\naws_secret_access_key = secret\naws_session_token = session\nraw_expiration = 2100-01-01T00:00:00+00:00\n"
+        );
+    }
+}
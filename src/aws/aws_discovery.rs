@@ -0,0 +1,324 @@
+use std::fs;
+use std::sync::Arc;
+
+use dialoguer::{FuzzySelect, Input};
+use serde::Deserialize;
+
+use crate::{
+    aws::aws_sso::AwsSso,
+    command::{self, CommandLine, CommandRunner},
+    constants,
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+};
+
+/// Response shape from `aws sso list-accounts`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListAccountsResponse {
+    account_list: Option<Vec<AccountInfo>>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountInfo {
+    account_id: String,
+    account_name: Option<String>,
+}
+
+/// Response shape from `aws sso list-account-roles`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListAccountRolesResponse {
+    role_list: Option<Vec<RoleInfo>>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RoleInfo {
+    role_name: String,
+}
+
+/// An account/role pair the SSO user is entitled to.
+#[derive(Debug, Clone)]
+pub struct DiscoveredRole {
+    pub account_id: String,
+    pub account_name: String,
+    pub role_name: String,
+}
+
+impl DiscoveredRole {
+    /// Profile name following the `<account name>.<role name>` convention.
+    pub fn profile_name(&self) -> String {
+        format!("{}.{}", self.account_name, self.role_name).replace(' ', "-")
+    }
+
+    pub fn profile_block(&self, region: &str) -> String {
+        format!(
+            "[profile {}]\nsso_session = {}\nsso_account_id = {}\nsso_role_name = {}\nregion = {}\n",
+            self.profile_name(),
+            constants::SSO_SESSION,
+            self.account_id,
+            self.role_name,
+            region
+        )
+    }
+}
+
+pub struct AwsDiscovery {
+    logger: Logger,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl AwsDiscovery {
+    pub fn new() -> Self {
+        Self::with_runner(command::default_runner())
+    }
+
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
+            logger: Logger::new(),
+            runner,
+        }
+    }
+
+    /// Lets the user pick any account and role they are entitled to through SSO.
+    pub fn select_role(&self) -> CustomResult<DiscoveredRole> {
+        let token = self.access_token()?;
+
+        let accounts = self.list_accounts(&token)?;
+        let account_labels: Vec<String> = accounts
+            .iter()
+            .map(|account| {
+                format!(
+                    "{} ({})",
+                    account.account_name.as_deref().unwrap_or("unnamed"),
+                    account.account_id
+                )
+            })
+            .collect();
+
+        let selection = FuzzySelect::new()
+            .with_prompt("Select an AWS account")
+            .items(&account_labels)
+            .default(0)
+            .interact()
+            .map_err(|err| CustomError::Cancelled(format!("Account selection failed: {}", err)))?;
+        let account = &accounts[selection];
+
+        let roles = self.list_account_roles(&token, &account.account_id)?;
+        let selection = FuzzySelect::new()
+            .with_prompt("Select a role")
+            .items(&roles)
+            .default(0)
+            .interact()
+            .map_err(|err| CustomError::Cancelled(format!("Role selection failed: {}", err)))?;
+
+        let role = DiscoveredRole {
+            account_id: account.account_id.clone(),
+            account_name: account
+                .account_name
+                .clone()
+                .unwrap_or_else(|| account.account_id.clone()),
+            role_name: roles[selection].clone(),
+        };
+        self.logger.info(format!(
+            "Selected role '{}' in account {}",
+            role.role_name, role.account_id
+        ));
+
+        Ok(role)
+    }
+
+    /// Every account/role pair the SSO user is entitled to.
+    pub fn all_roles(&self) -> CustomResult<Vec<DiscoveredRole>> {
+        let token = self.access_token()?;
+        let mut roles = Vec::new();
+
+        for account in self.list_accounts(&token)? {
+            for role_name in self.list_account_roles(&token, &account.account_id)? {
+                roles.push(DiscoveredRole {
+                    account_id: account.account_id.clone(),
+                    account_name: account
+                        .account_name
+                        .clone()
+                        .unwrap_or_else(|| account.account_id.clone()),
+                    role_name,
+                });
+            }
+        }
+
+        Ok(roles)
+    }
+
+    /// Appends `[profile ...]` blocks for `roles` to ~/.aws/config, skipping
+    /// profiles that already exist there.
+    pub fn write_profiles(&self, roles: &[DiscoveredRole]) -> CustomResult<()> {
+        let region: String = Input::new()
+            .with_prompt("Default region for the generated profiles")
+            .default(constants::SSO_REGION.to_string())
+            .interact_text()
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
+
+        let config_path = dirs::home_dir()
+            .ok_or_else(|| {
+                CustomError::CommandExecution("Failed to get home directory".to_string())
+            })?
+            .join(".aws/config");
+
+        let mut contents = match fs::read_to_string(&config_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(CustomError::Config(format!(
+                    "Could not read ~/.aws/config: {}",
+                    err
+                )))
+            }
+        };
+
+        let mut written = 0;
+        for role in roles {
+            let header = format!("[profile {}]", role.profile_name());
+            if contents.lines().any(|line| line.trim() == header) {
+                self.logger.info(format!(
+                    "Profile '{}' already exists, skipping",
+                    role.profile_name()
+                ));
+                continue;
+            }
+
+            if !contents.is_empty() && !contents.ends_with("\n\n") {
+                contents.push_str(if contents.ends_with('\n') {
+                    "\n"
+                } else {
+                    "\n\n"
+                });
+            }
+            contents.push_str(&role.profile_block(region.trim()));
+            written += 1;
+        }
+
+        if written > 0 {
+            file_utils::write_private_atomic(&config_path, &contents)?;
+        }
+        self.logger
+            .info(format!("Wrote {} profile(s) to ~/.aws/config", written));
+
+        Ok(())
+    }
+
+    fn access_token(&self) -> CustomResult<String> {
+        let sso =
+            AwsSso::new(constants::DEV_ROLE.to_string()).with_runner(Arc::clone(&self.runner));
+        sso.login()?;
+        sso.get_latest_sso_token()
+    }
+
+    fn list_accounts(&self, token: &str) -> CustomResult<Vec<AccountInfo>> {
+        self.logger.debug("Listing SSO accounts");
+
+        let mut accounts = Vec::new();
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut input = serde_json::json!({ "accessToken": token });
+            if let Some(ref token) = next_token {
+                input["nextToken"] = token.clone().into();
+            }
+
+            let output = self
+                .runner
+                .execute(&Self::sso_command("list-accounts", input))?;
+            let response: ListAccountsResponse =
+                serde_json::from_slice(&output.stdout).map_err(|err| {
+                    CustomError::Parse(format!("Failed to parse list-accounts response: {}", err))
+                })?;
+
+            accounts.extend(response.account_list.unwrap_or_default());
+
+            match response.next_token {
+                Some(token) if !token.is_empty() => next_token = Some(token),
+                _ => break,
+            }
+        }
+
+        if accounts.is_empty() {
+            return Err(CustomError::CommandExecution(
+                "No accounts available for the SSO user".to_string(),
+            ));
+        }
+
+        accounts.sort_by(|a, b| a.account_name.cmp(&b.account_name));
+        Ok(accounts)
+    }
+
+    /// `aws sso <operation>` with its parameters, including the access token,
+    /// read from stdin instead of the command line.
+    fn sso_command(operation: &str, input: serde_json::Value) -> CommandLine {
+        CommandLine::new("aws")
+            .args([
+                "sso",
+                operation,
+                "--cli-input-json",
+                "file:///dev/stdin",
+                "--region",
+                constants::SSO_REGION,
+                "--output",
+                "json",
+            ])
+            .stdin(input.to_string())
+    }
+
+    fn list_account_roles(&self, token: &str, account_id: &str) -> CustomResult<Vec<String>> {
+        self.logger
+            .debug(format!("Listing SSO roles for account {}", account_id));
+
+        let mut roles = Vec::new();
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut input = serde_json::json!({ "accessToken": token, "accountId": account_id });
+            if let Some(ref token) = next_token {
+                input["nextToken"] = token.clone().into();
+            }
+
+            let output = self
+                .runner
+                .execute(&Self::sso_command("list-account-roles", input))?;
+            let response: ListAccountRolesResponse = serde_json::from_slice(&output.stdout)
+                .map_err(|err| {
+                    CustomError::Parse(format!(
+                        "Failed to parse list-account-roles response: {}",
+                        err
+                    ))
+                })?;
+
+            roles.extend(
+                response
+                    .role_list
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|role| role.role_name),
+            );
+
+            match response.next_token {
+                Some(token) if !token.is_empty() => next_token = Some(token),
+                _ => break,
+            }
+        }
+
+        if roles.is_empty() {
+            return Err(CustomError::CommandExecution(format!(
+                "No roles available in account {}",
+                account_id
+            )));
+        }
+
+        roles.sort();
+        Ok(roles)
+    }
+}
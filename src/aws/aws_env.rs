@@ -0,0 +1,181 @@
+use std::process::Command;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    aws::{
+        aws_sso::{AwsSso, RoleCredentials},
+        aws_sts::AssumeRoleOptions,
+    },
+    command::{self, CommandLine},
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+};
+
+/// Output flavour for `raw env`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EnvFormat {
+    Bash,
+    Zsh,
+    Fish,
+    Dotenv,
+}
+
+/// Document expected by the AWS SDKs from a `credential_process`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessCredentials<'a> {
+    version: u8,
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    session_token: &'a str,
+    expiration: String,
+}
+
+pub struct AwsEnv {
+    logger: Logger,
+    profile: String,
+    assume_role: Option<AssumeRoleOptions>,
+}
+
+impl AwsEnv {
+    pub fn new(profile: String) -> Self {
+        Self {
+            logger: Logger::new(),
+            profile,
+            assume_role: None,
+        }
+    }
+
+    pub fn with_assume_role(mut self, assume_role: Option<AssumeRoleOptions>) -> Self {
+        self.assume_role = assume_role;
+        self
+    }
+
+    /// Prints the role credentials of the profile to stdout so they can be
+    /// `eval`-ed by the parent shell or redirected into a `.env` file.
+    pub fn print(&self, format: EnvFormat) -> CustomResult<()> {
+        let creds = self.resolve_credentials()?;
+
+        print!("{}", self.render(&creds, format));
+
+        Ok(())
+    }
+
+    /// Prints the role credentials of the profile in the `credential_process`
+    /// format, so SDKs configured with `credential_process = raw credential-process
+    /// --profile <name>` reuse the SSO session of this tool.
+    pub fn print_credential_process(&self) -> CustomResult<()> {
+        let creds = self.resolve_credentials()?;
+
+        let document = ProcessCredentials {
+            version: 1,
+            access_key_id: &creds.access_key_id,
+            secret_access_key: &creds.secret_access_key,
+            session_token: &creds.session_token,
+            expiration: creds.expires_at()?.to_rfc3339(),
+        };
+
+        let json = serde_json::to_string_pretty(&document).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to serialize credentials: {}", err))
+        })?;
+        println!("{}", json);
+
+        Ok(())
+    }
+
+    /// Runs `command` as a child process with the role credentials injected
+    /// and returns its exit code.
+    pub fn exec(&self, command: &[String]) -> CustomResult<i32> {
+        let (program, args) = command.split_first().ok_or_else(|| {
+            CustomError::CommandExecution("No command given to execute".to_string())
+        })?;
+
+        let creds = self.resolve_credentials()?;
+
+        self.logger.debug(format!(
+            "Executing '{}' with profile '{}'",
+            program, self.profile
+        ));
+
+        // The command itself bypasses the runner, so dry-run stops here
+        let masked: Vec<String> = self
+            .variables(&creds)
+            .iter()
+            .map(|(key, _)| format!("{}=***", key))
+            .collect();
+        if file_utils::skip_write(
+            "run",
+            format!(
+                "{} {}",
+                masked.join(" "),
+                CommandLine::new(program).args(args)
+            ),
+        ) {
+            return Ok(0);
+        }
+
+        let status = command::run_foreground(
+            Command::new(program)
+                .args(args)
+                .envs(self.variables(&creds)),
+        )
+        .map_err(|err| {
+            let message = format!("Failed to execute '{}': {}", program, err);
+            if err.kind() == std::io::ErrorKind::NotFound {
+                CustomError::ToolMissing(message)
+            } else {
+                CustomError::CommandExecution(message)
+            }
+        })?;
+
+        // A child killed by a signal has no exit code, report it the way
+        // shells do, e.g. 130 for Ctrl-C
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return Ok(128 + signal);
+            }
+        }
+        Ok(status.code().unwrap_or(1))
+    }
+
+    fn resolve_credentials(&self) -> CustomResult<RoleCredentials> {
+        self.logger.debug(format!(
+            "Resolving credentials for profile '{}'",
+            self.profile
+        ));
+
+        let sso = AwsSso::new(self.profile.clone()).with_assume_role(self.assume_role.clone());
+        sso.login()?;
+        sso.get_sso_credentials()
+    }
+
+    fn variables<'a>(&self, creds: &'a RoleCredentials) -> [(&'static str, &'a str); 3] {
+        [
+            ("AWS_ACCESS_KEY_ID", creds.access_key_id.as_str()),
+            ("AWS_SECRET_ACCESS_KEY", creds.secret_access_key.as_str()),
+            ("AWS_SESSION_TOKEN", creds.session_token.as_str()),
+        ]
+    }
+
+    fn render(&self, creds: &RoleCredentials, format: EnvFormat) -> String {
+        self.variables(creds)
+            .iter()
+            .map(|(key, value)| match format {
+                EnvFormat::Bash | EnvFormat::Zsh => {
+                    format!("export {}='{}'\n", key, value.replace('\'', "'\\''"))
+                }
+                EnvFormat::Fish => format!(
+                    "set -gx {} '{}'\n",
+                    key,
+                    value.replace('\\', "\\\\").replace('\'', "\\'")
+                ),
+                EnvFormat::Dotenv => format!("{}={}\n", key, value),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs;
+use std::sync::Arc;
+
+use crate::{
+    aws::{
+        aws_codeartifact::CodeArtifactState, aws_credentials_file::CredentialsFile,
+        aws_logs::RAW_CREDENTIALS_ENTRY, aws_role_cache::RoleCredentialCache, aws_sso::AwsSso,
+    },
+    command::{self, CommandLine, CommandRunner},
+    constants,
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+    secret_store,
+};
+
+pub struct AwsLogout {
+    logger: Logger,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl AwsLogout {
+    pub fn new() -> Self {
+        Self::with_runner(command::default_runner())
+    }
+
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
+            logger: Logger::new(),
+            runner,
+        }
+    }
+
+    /// Revokes the SSO session and removes every credential this tool cached
+    /// or wrote. npm/pip are only reset to their defaults on request.
+    pub fn run(&self, reset_npm: bool, reset_pip: bool) -> CustomResult<()> {
+        self.logger.info("Logging out of AWS SSO");
+        if let Err(err) = self
+            .runner
+            .execute(&CommandLine::new("aws").args(["sso", "logout"]))
+        {
+            // Keep cleaning up local state even if the token can't be revoked
+            self.logger
+                .warn(format!("Failed to revoke SSO session: {}", err));
+        }
+
+        self.remove_sso_tokens()?;
+
+        RoleCredentialCache::new()?.clear()?;
+        secret_store::open()?.delete(RAW_CREDENTIALS_ENTRY)?;
+        self.logger
+            .info("Cleared cached role and stored raw credentials");
+
+        for profile in CredentialsFile::new()?.remove_managed_profiles()? {
+            self.logger
+                .info(format!("Removed credentials profile '{}'", profile));
+        }
+
+        if reset_npm {
+            self.reset_npm()?;
+        }
+
+        if reset_pip {
+            self.reset_pip()?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_sso_tokens(&self) -> CustomResult<()> {
+        let sso =
+            AwsSso::new(constants::DEV_ROLE.to_string()).with_runner(Arc::clone(&self.runner));
+
+        for token in sso.sso_tokens()? {
+            if token.start_url != constants::SSO_START_URL {
+                continue;
+            }
+
+            if file_utils::skip_write("remove", token.path.display()) {
+                continue;
+            }
+
+            fs::remove_file(&token.path).map_err(|err| {
+                CustomError::CommandExecution(format!(
+                    "Failed to remove SSO cache file {}: {}",
+                    token.path.display(),
+                    err
+                ))
+            })?;
+            self.logger
+                .info(format!("Removed SSO cache file {}", token.path.display()));
+        }
+
+        Ok(())
+    }
+
+    /// Drops the CodeArtifact registry and auth token lines that
+    /// `aws codeartifact login --tool npm` adds to ~/.npmrc.
+    fn reset_npm(&self) -> CustomResult<()> {
+        let npmrc = dirs::home_dir()
+            .ok_or_else(|| {
+                CustomError::CommandExecution("Failed to get home directory".to_string())
+            })?
+            .join(".npmrc");
+
+        let contents = match fs::read_to_string(&npmrc) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(CustomError::CommandExecution(format!(
+                    "Failed to read {}: {}",
+                    npmrc.display(),
+                    err
+                )))
+            }
+        };
+
+        let kept: Vec<&str> = contents
+            .lines()
+            .filter(|line| !line.contains(".codeartifact."))
+            .collect();
+
+        if kept.len() != contents.lines().count() {
+            let mut output = kept.join("\n");
+            output.push('\n');
+            file_utils::write_private_atomic(&npmrc, &output)?;
+        }
+
+        CodeArtifactState::new()?.remove("npm")?;
+        self.logger.info("Reset npm CodeArtifact configuration");
+
+        Ok(())
+    }
+
+    /// Unsets the index URL `aws codeartifact login --tool pip` configures,
+    /// leaving custom indexes untouched.
+    fn reset_pip(&self) -> CustomResult<()> {
+        let index_url = self
+            .runner
+            .execute(&CommandLine::new("pip").args(["config", "get", "global.index-url"]))
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            .unwrap_or_default();
+
+        if index_url.contains(".codeartifact.") {
+            self.runner.execute(&CommandLine::new("pip").args([
+                "config",
+                "unset",
+                "global.index-url",
+            ]))?;
+        }
+
+        CodeArtifactState::new()?.remove("pip")?;
+        self.logger.info("Reset pip CodeArtifact configuration");
+
+        Ok(())
+    }
+}
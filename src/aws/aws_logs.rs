@@ -1,16 +1,20 @@
 use std::fs;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use chrono::DateTime;
-use dialoguer::{FuzzySelect, Input};
+use dialoguer::{Confirm, FuzzySelect, Input};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    aws::aws_sso::AwsSso,
+    aws::{aws_discovery::AwsDiscovery, aws_session::AwsSession, aws_sso::AwsSso},
+    command::{self, CommandLine, CommandRunner, RetryPolicy},
     config::AppConfig,
+    constants,
     custom_error::{CustomError, CustomResult},
+    file_utils,
     logger::Logger,
-    zsh_command::ZshCommand,
+    secret_store,
 };
 
 /// A single log event with the fields we persist to JSON.
@@ -52,30 +56,56 @@ struct LogGroup {
     log_group_name: Option<String>,
 }
 
-const MAX_RETRIES: u32 = 3;
+/// Credentials entered for `logs-raw`, optionally kept in the secret store.
+#[derive(Debug, Serialize, Deserialize)]
+struct RawCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    region: String,
+}
+
+/// Secret store entry holding the `logs-raw` credentials.
+pub const RAW_CREDENTIALS_ENTRY: &str = "raw-credentials";
 const FETCH_TIMEOUT: Duration = Duration::from_secs(300);
 const OUTPUT_FILE: &str = "logs.json";
 
 pub struct AwsLogs {
     logger: Logger,
-    zsh_command: ZshCommand,
+    runner: Arc<dyn CommandRunner>,
+    session: AwsSession,
+    retry: RetryPolicy,
 }
 
 impl AwsLogs {
     pub fn new() -> Self {
+        Self::with_runner(command::default_runner())
+    }
+
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
         Self {
             logger: Logger::new(),
-            zsh_command: ZshCommand::new(),
+            runner,
+            session: AwsSession::new(),
+            retry: RetryPolicy::new(),
         }
     }
 
     /// Main entry point for the `logs` command.
-    pub fn run(&self) -> CustomResult<()> {
+    /// With `discover`, the account and role are picked from SSO directly.
+    pub fn run(&self, discover: bool) -> CustomResult<()> {
         let config = AppConfig::load();
 
         if config.auth.disable_sso {
             self.logger
                 .info("SSO disabled via config — using local AWS credentials");
+        } else if discover {
+            // Step 1-2: Role discovery and SSO authentication
+            let role = AwsDiscovery::with_runner(Arc::clone(&self.runner)).select_role()?;
+            let sso = AwsSso::for_role(&role.account_id, &role.role_name, constants::SSO_REGION)
+                .with_runner(Arc::clone(&self.runner));
+            sso.set_sso_credentials()?;
+            self.logger.info("SSO authentication successful");
         } else {
             // Step 1: Profile selection
             let profile = self.select_profile()?;
@@ -129,7 +159,8 @@ impl AwsLogs {
     // ─── Profile Selection ───────────────────────────────────────────────
 
     fn select_profile(&self) -> CustomResult<String> {
-        self.logger.debug("Reading AWS config for profile selection");
+        self.logger
+            .debug("Reading AWS config for profile selection");
 
         let config_path = dirs::home_dir()
             .ok_or_else(|| {
@@ -137,12 +168,8 @@ impl AwsLogs {
             })?
             .join(".aws/config");
 
-        let contents = fs::read_to_string(&config_path).map_err(|err| {
-            CustomError::CommandExecution(format!(
-                "Could not read ~/.aws/config: {}",
-                err
-            ))
-        })?;
+        let contents = fs::read_to_string(&config_path)
+            .map_err(|err| CustomError::Config(format!("Could not read ~/.aws/config: {}", err)))?;
 
         let profiles: Vec<String> = contents
             .lines()
@@ -162,7 +189,7 @@ impl AwsLogs {
             .collect();
 
         if profiles.is_empty() {
-            return Err(CustomError::CommandExecution(
+            return Err(CustomError::Config(
                 "No profiles found in ~/.aws/config".to_string(),
             ));
         }
@@ -172,13 +199,10 @@ impl AwsLogs {
             .items(&profiles)
             .default(0)
             .interact()
-            .map_err(|err| {
-                CustomError::CommandExecution(format!("Profile selection failed: {}", err))
-            })?;
+            .map_err(|err| CustomError::Cancelled(format!("Profile selection failed: {}", err)))?;
 
         let selected = profiles[selection].clone();
-        self.logger
-            .info(format!("Selected profile: {}", &selected));
+        self.logger.info(format!("Selected profile: {}", &selected));
         Ok(selected)
     }
 
@@ -188,7 +212,7 @@ impl AwsLogs {
         self.logger
             .info(format!("Authenticating with profile '{}'", profile));
 
-        let sso = AwsSso::new(profile.to_string());
+        let sso = AwsSso::new(profile.to_string()).with_runner(Arc::clone(&self.runner));
         sso.login()?;
         sso.set_sso_credentials()?;
 
@@ -199,44 +223,83 @@ impl AwsLogs {
     // ─── Raw Authentication (manual tokens) ──────────────────────────────
 
     fn authenticate_raw(&self) -> CustomResult<()> {
+        let store = secret_store::open()?;
+        let stored: Option<RawCredentials> = store
+            .get(RAW_CREDENTIALS_ENTRY)?
+            .and_then(|json| serde_json::from_str(&json).ok());
+
+        let reuse = match stored {
+            Some(_) => Confirm::new()
+                .with_prompt("Reuse the stored AWS credentials?")
+                .default(true)
+                .interact()
+                .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?,
+            None => false,
+        };
+
+        let creds = match stored {
+            Some(creds) if reuse => creds,
+            _ => {
+                let creds = self.prompt_raw_credentials()?;
+
+                let save = Confirm::new()
+                    .with_prompt("Store these credentials for the next run?")
+                    .default(false)
+                    .interact()
+                    .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
+                if save {
+                    let json = serde_json::to_string(&creds).map_err(|err| {
+                        CustomError::CommandExecution(format!(
+                            "Failed to serialize credentials: {}",
+                            err
+                        ))
+                    })?;
+                    store.set(RAW_CREDENTIALS_ENTRY, &json)?;
+                }
+
+                creds
+            }
+        };
+
+        std::env::set_var("AWS_ACCESS_KEY_ID", &creds.access_key_id);
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", &creds.secret_access_key);
+        std::env::set_var("AWS_SESSION_TOKEN", &creds.session_token);
+        std::env::set_var("AWS_DEFAULT_REGION", &creds.region);
+
+        self.logger.info("AWS credentials set from manual input");
+        Ok(())
+    }
+
+    fn prompt_raw_credentials(&self) -> CustomResult<RawCredentials> {
         self.logger.info("Prompting for AWS credentials");
 
         let access_key_id: String = Input::new()
             .with_prompt("AWS_ACCESS_KEY_ID")
             .interact_text()
-            .map_err(|err| {
-                CustomError::CommandExecution(format!("Input failed: {}", err))
-            })?;
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
 
         let secret_access_key: String = Input::new()
             .with_prompt("AWS_SECRET_ACCESS_KEY")
             .interact_text()
-            .map_err(|err| {
-                CustomError::CommandExecution(format!("Input failed: {}", err))
-            })?;
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
 
         let session_token: String = Input::new()
             .with_prompt("AWS_SESSION_TOKEN")
             .interact_text()
-            .map_err(|err| {
-                CustomError::CommandExecution(format!("Input failed: {}", err))
-            })?;
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
 
         let region: String = Input::new()
             .with_prompt("AWS_DEFAULT_REGION")
             .default("eu-west-1".to_string())
             .interact_text()
-            .map_err(|err| {
-                CustomError::CommandExecution(format!("Input failed: {}", err))
-            })?;
-
-        std::env::set_var("AWS_ACCESS_KEY_ID", access_key_id.trim());
-        std::env::set_var("AWS_SECRET_ACCESS_KEY", secret_access_key.trim());
-        std::env::set_var("AWS_SESSION_TOKEN", session_token.trim());
-        std::env::set_var("AWS_DEFAULT_REGION", region.trim());
-
-        self.logger.info("AWS credentials set from manual input");
-        Ok(())
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
+
+        Ok(RawCredentials {
+            access_key_id: access_key_id.trim().to_string(),
+            secret_access_key: secret_access_key.trim().to_string(),
+            session_token: session_token.trim().to_string(),
+            region: region.trim().to_string(),
+        })
     }
 
     // ─── Log Group Selection ─────────────────────────────────────────────
@@ -249,15 +312,17 @@ impl AwsLogs {
 
         loop {
             let mut command =
-                String::from("aws logs describe-log-groups --output json");
+                CommandLine::new("aws").args(["logs", "describe-log-groups", "--output", "json"]);
             if let Some(ref token) = next_token {
-                command.push_str(&format!(" --next-token '{}'", token));
+                command = command.arg("--next-token").arg(token);
             }
 
-            let output = self.zsh_command.execute(&command)?;
-            let response: DescribeLogGroupsResponse =
-                serde_json::from_slice(&output.stdout).map_err(|err| {
-                    CustomError::CommandExecution(format!(
+            let output = self
+                .session
+                .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+            let response: DescribeLogGroupsResponse = serde_json::from_slice(&output.stdout)
+                .map_err(|err| {
+                    CustomError::Parse(format!(
                         "Failed to parse describe-log-groups response: {}",
                         err
                     ))
@@ -294,7 +359,7 @@ impl AwsLogs {
             .default(0)
             .interact()
             .map_err(|err| {
-                CustomError::CommandExecution(format!("Log group selection failed: {}", err))
+                CustomError::Cancelled(format!("Log group selection failed: {}", err))
             })?;
 
         let selected = filtered_groups[selection].clone();
@@ -336,7 +401,7 @@ impl AwsLogs {
             .default(0)
             .interact()
             .map_err(|err| {
-                CustomError::CommandExecution(format!("Environment selection failed: {}", err))
+                CustomError::Cancelled(format!("Environment selection failed: {}", err))
             })?;
 
         let selected_env = &envs[selection];
@@ -387,7 +452,8 @@ impl AwsLogs {
     // ─── Time Range Prompts ──────────────────────────────────────────────
 
     fn prompt_time_range(&self) -> CustomResult<(i64, i64)> {
-        let start_ms = self.prompt_timestamp("Enter start time (ISO 8601, e.g. 2024-01-15T10:00:00Z)")?;
+        let start_ms =
+            self.prompt_timestamp("Enter start time (ISO 8601, e.g. 2024-01-15T10:00:00Z)")?;
 
         loop {
             let end_ms =
@@ -407,9 +473,7 @@ impl AwsLogs {
             let input: String = Input::new()
                 .with_prompt(prompt)
                 .interact_text()
-                .map_err(|err| {
-                    CustomError::CommandExecution(format!("Input failed: {}", err))
-                })?;
+                .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
 
             match DateTime::parse_from_rfc3339(&input) {
                 Ok(dt) => return Ok(dt.timestamp_millis()),
@@ -429,9 +493,7 @@ impl AwsLogs {
             .with_prompt("Enter logId to filter by (leave empty to skip)")
             .allow_empty(true)
             .interact_text()
-            .map_err(|err| {
-                CustomError::CommandExecution(format!("Input failed: {}", err))
-            })?;
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
 
         let trimmed = input.trim().to_string();
         if trimmed.is_empty() {
@@ -468,27 +530,34 @@ impl AwsLogs {
                 break;
             }
 
-            let mut command = format!(
-                "aws logs filter-log-events --log-group-name '{}' --start-time {} --end-time {} --output json",
-                log_group, start_ms, end_ms
-            );
+            let mut command = CommandLine::new("aws").args([
+                "logs",
+                "filter-log-events",
+                "--log-group-name",
+                log_group,
+                "--start-time",
+                &start_ms.to_string(),
+                "--end-time",
+                &end_ms.to_string(),
+                "--output",
+                "json",
+            ]);
 
             if let Some(id) = log_id {
-                command.push_str(&format!(
-                    " --filter-pattern '{{ $.logId = \"{}\" }}'",
-                    id
-                ));
+                command = command
+                    .arg("--filter-pattern")
+                    .arg(format!("{{ $.logId = {} }}", serde_json::Value::from(id)));
             }
 
             if let Some(ref token) = next_token {
-                command.push_str(&format!(" --next-token '{}'", token));
+                command = command.arg("--next-token").arg(token);
             }
 
-            let output = self.execute_with_retry(&command)?;
+            let output = self.session.run(|| self.execute_with_retry(&command))?;
 
             let response: FilterLogEventsResponse =
                 serde_json::from_slice(&output).map_err(|err| {
-                    CustomError::CommandExecution(format!(
+                    CustomError::Parse(format!(
                         "Failed to parse filter-log-events response: {}",
                         err
                     ))
@@ -515,33 +584,9 @@ impl AwsLogs {
         Ok(all_events)
     }
 
-    fn execute_with_retry(&self, command: &str) -> CustomResult<Vec<u8>> {
-        let mut last_error = None;
-
-        for attempt in 0..=MAX_RETRIES {
-            if attempt > 0 {
-                let delay = Duration::from_secs(1 << (attempt - 1)); // 1s, 2s, 4s
-                self.logger.warn(format!(
-                    "Retry attempt {}/{} after {}s delay",
-                    attempt,
-                    MAX_RETRIES,
-                    delay.as_secs()
-                ));
-                std::thread::sleep(delay);
-            }
-
-            match self.zsh_command.execute(command) {
-                Ok(output) => return Ok(output.stdout),
-                Err(e) => {
-                    last_error = Some(e);
-                    continue;
-                }
-            }
-        }
-
-        Err(last_error.unwrap_or_else(|| {
-            CustomError::CommandExecution("All retry attempts failed".to_string())
-        }))
+    fn execute_with_retry(&self, command: &CommandLine) -> CustomResult<Vec<u8>> {
+        let output = self.retry.run(|| self.runner.execute(command))?;
+        Ok(output.stdout)
     }
 
     // ─── File Output ─────────────────────────────────────────────────────
@@ -551,6 +596,10 @@ impl AwsLogs {
             CustomError::CommandExecution(format!("Failed to serialize log events: {}", err))
         })?;
 
+        if file_utils::skip_write("write", OUTPUT_FILE) {
+            return Ok(());
+        }
+
         fs::write(OUTPUT_FILE, &json).map_err(|err| {
             CustomError::CommandExecution(format!("Failed to write {}: {}", OUTPUT_FILE, err))
         })?;
@@ -565,13 +614,73 @@ impl AwsLogs {
                 abs_path.display()
             );
         } else {
-            println!(
-                "Saved {} events to {}",
-                events.len(),
-                abs_path.display()
-            );
+            println!("Saved {} events to {}", events.len(), abs_path.display());
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::FakeRunner;
+
+    fn filter_log_events() -> CommandLine {
+        CommandLine::new("aws").args([
+            "logs",
+            "filter-log-events",
+            "--log-group-name",
+            "/aws/lambda/conform5-qa-101-api",
+            "--start-time",
+            "1000",
+            "--end-time",
+            "2000",
+            "--output",
+            "json",
+            "--filter-pattern",
+            r#"{ $.logId = "a\"b" }"#,
+        ])
+    }
+
+    #[test]
+    fn fetches_every_page_of_log_events() {
+        let runner = Arc::new(
+            FakeRunner::new()
+                .expect(
+                    filter_log_events(),
+                    r#"{"events": [{"timestamp": 1500, "message": "first", "logStreamName": "s1"}], "nextToken": "page2"}"#,
+                )
+                .expect(
+                    filter_log_events().args(["--next-token", "page2"]),
+                    r#"{"events": [{"message": "second"}], "nextToken": ""}"#,
+                ),
+        );
+
+        let events = AwsLogs::with_runner(runner.clone())
+            .fetch_logs("/aws/lambda/conform5-qa-101-api", 1000, 2000, Some("a\"b"))
+            .unwrap();
+
+        let messages: Vec<&str> = events.iter().map(|event| event.message.as_str()).collect();
+        assert_eq!(messages, ["first", "second"]);
+        assert_eq!(events[0].log_stream_name, "s1");
+        assert_eq!(events[1].timestamp, 0);
+        runner.verify().unwrap();
+    }
+
+    #[test]
+    fn extracts_environment_prefix() {
+        let logs = AwsLogs::with_runner(Arc::new(FakeRunner::new()));
+
+        assert_eq!(
+            logs.extract_env_prefix("/aws/lambda/conform5-qa-101-myFunction"),
+            Some("conform5-qa-101".to_string())
+        );
+        assert_eq!(
+            logs.extract_env_prefix("/aws/ecs/conform5-prod-200-service"),
+            Some("conform5-prod-200".to_string())
+        );
+        assert_eq!(logs.extract_env_prefix("/aws/lambda/my-function"), None);
+        assert_eq!(logs.extract_env_prefix("/aws/lambda/123-qa-service"), None);
+    }
+}
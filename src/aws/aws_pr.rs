@@ -1,9 +1,18 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use dialoguer::Confirm;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use crate::{
+    aws::{aws_session::AwsSession, aws_sts::AwsSts},
+    command::{self, CommandLine, CommandRunner, RetryPolicy},
+    config::{AppConfig, ApprovalRuleConfig},
     custom_error::{CustomError, CustomResult},
     logger::Logger,
-    zsh_command::ZshCommand,
+    pr_description::PrDescription,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -15,58 +24,421 @@ struct Commit {
 #[serde(rename_all = "camelCase")]
 struct PullRequest {
     pull_request_id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    pull_request_status: String,
+    #[serde(default)]
+    author_arn: String,
+    #[serde(default)]
+    revision_id: String,
+    #[serde(default)]
+    pull_request_targets: Vec<PullRequestTarget>,
+    #[serde(default)]
+    approval_rules: Vec<ApprovalRule>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApprovalRule {
+    approval_rule_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestTarget {
+    repository_name: String,
+    source_reference: String,
+    destination_reference: String,
+    #[serde(default)]
+    source_commit: String,
+    #[serde(default)]
+    destination_commit: String,
+    #[serde(default)]
+    merge_metadata: Option<MergeMetadata>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeMetadata {
+    is_merged: bool,
+    #[serde(default)]
+    merged_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestIds {
+    pull_request_ids: Vec<String>,
+    #[serde(default)]
+    next_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApprovalStates {
+    approvals: Vec<Approval>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Approval {
+    user_arn: String,
+    approval_state: String,
+}
+
+/// Pull request state filter for `raw pr list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PrStatus {
+    Open,
+    Closed,
+}
+
+impl PrStatus {
+    fn as_aws(&self) -> &'static str {
+        match self {
+            Self::Open => "OPEN",
+            Self::Closed => "CLOSED",
+        }
+    }
+}
+
+/// How `raw pr merge` combines the source branch into the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MergeStrategy {
+    FastForward,
+    Squash,
+    ThreeWay,
+}
+
+impl MergeStrategy {
+    fn merge_option(&self) -> &'static str {
+        match self {
+            Self::FastForward => "FAST_FORWARD_MERGE",
+            Self::Squash => "SQUASH_MERGE",
+            Self::ThreeWay => "THREE_WAY_MERGE",
+        }
+    }
+
+    fn command(&self) -> &'static str {
+        match self {
+            Self::FastForward => "merge-pull-request-by-fast-forward",
+            Self::Squash => "merge-pull-request-by-squash",
+            Self::ThreeWay => "merge-pull-request-by-three-way",
+        }
+    }
+}
+
+/// What `raw create-pr` asked for; unset values are derived from git.
+#[derive(Debug, Clone)]
+pub struct CreatePrOptions {
+    pub title: Option<String>,
+    pub source_branch: Option<String>,
+    pub target: String,
+    pub merge_strategy: MergeStrategy,
+    pub description: Option<String>,
+    pub edit: bool,
+    pub abort_early: bool,
+    /// Name of an approval rule in `AppConfig` to attach
+    pub approval_rule: Option<String>,
+    /// Approval rule content as inline JSON to attach
+    pub approval_rule_json: Option<String>,
+}
+
+/// Commit details for squash and three-way merges.
+#[derive(Debug, Default)]
+pub struct MergeCommit {
+    pub message: Option<String>,
+    pub author_name: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApprovalRulesEvaluation {
+    evaluation: Evaluation,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Evaluation {
+    approved: bool,
+    overridden: bool,
+    #[serde(default)]
+    approval_rules_not_satisfied: Vec<String>,
+    #[serde(default)]
+    approval_rules_satisfied: Vec<String>,
+}
+
+/// Body of an approval rule, as accepted by `--approval-rule-content`.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApprovalRuleContent {
+    version: &'static str,
+    statements: Vec<ApprovalRuleStatement>,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApprovalRuleStatement {
+    #[serde(rename = "Type")]
+    kind: &'static str,
+    number_of_approvals_needed: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    approval_pool_members: Vec<String>,
+}
+
+impl From<&ApprovalRuleConfig> for ApprovalRuleContent {
+    fn from(rule: &ApprovalRuleConfig) -> Self {
+        Self {
+            version: "2018-11-08",
+            statements: vec![ApprovalRuleStatement {
+                kind: "Approvers",
+                number_of_approvals_needed: rule.approvals,
+                approval_pool_members: rule.pool.clone(),
+            }],
+        }
+    }
+}
+
+/// Name given to rules passed as inline JSON.
+const INLINE_APPROVAL_RULE_NAME: &str = "raw-approval-rule";
+
+/// Review verdicts for `update-pull-request-approval-state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalState {
+    Approve,
+    Revoke,
+}
+
+impl ApprovalState {
+    fn as_aws(&self) -> &'static str {
+        match self {
+            Self::Approve => "APPROVE",
+            Self::Revoke => "REVOKE",
+        }
+    }
+}
+
+/// File and line a pull request comment refers to.
+#[derive(Debug)]
+pub struct CommentLocation {
+    pub file_path: String,
+    pub line: Option<u64>,
+}
+
+/// `get-pull-request` calls issued concurrently while listing.
+const FETCH_BATCH_SIZE: usize = 10;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct PullRequestMergeConflicts {
     mergeable: bool,
+    #[serde(default)]
+    conflict_metadata_list: Vec<ConflictMetadata>,
+    #[serde(default)]
+    next_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ConflictMetadata {
+    file_path: String,
+    #[serde(default)]
+    number_of_conflicts: u32,
+    #[serde(default)]
+    is_binary_file: Option<BinaryFlags>,
+    #[serde(default)]
+    content_conflict: bool,
+    #[serde(default)]
+    file_mode_conflict: bool,
+    #[serde(default)]
+    object_type_conflict: bool,
+    #[serde(default)]
+    merge_operations: Option<MergeOperations>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BinaryFlags {
+    #[serde(default)]
+    source: bool,
+    #[serde(default)]
+    destination: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MergeOperations {
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    destination: Option<String>,
+}
+
+impl ConflictMetadata {
+    /// e.g. `content (3 hunks), source M / destination D`
+    fn describe(&self) -> String {
+        let mut kinds = Vec::new();
+        if self.content_conflict {
+            let binary = self
+                .is_binary_file
+                .as_ref()
+                .is_some_and(|flags| flags.source || flags.destination);
+            if binary {
+                kinds.push("binary content".to_string());
+            } else {
+                kinds.push(format!("content ({} hunks)", self.number_of_conflicts));
+            }
+        }
+        if self.file_mode_conflict {
+            kinds.push("file mode".to_string());
+        }
+        if self.object_type_conflict {
+            kinds.push("object type".to_string());
+        }
+        if kinds.is_empty() {
+            kinds.push("unknown".to_string());
+        }
+
+        match &self.merge_operations {
+            Some(operations) => format!(
+                "{}, source {} / destination {}",
+                kinds.join(", "),
+                operations.source.as_deref().unwrap_or("-"),
+                operations.destination.as_deref().unwrap_or("-")
+            ),
+            None => kinds.join(", "),
+        }
+    }
+}
+
+/// Where `create` stands once the steps before `create-pull-request` ran.
+enum Draft {
+    /// The open pull request for the same branches was updated, with its link
+    Updated(String),
+    New {
+        input: serde_json::Value,
+        approval_rule: Option<(String, String)>,
+    },
 }
 
 pub struct AwsPr {
     logger: Logger,
-    zsh_command: ZshCommand,
+    runner: Arc<dyn CommandRunner>,
+    session: AwsSession,
+    retry: RetryPolicy,
+    /// For calls that create something, see `RetryPolicy::throttling_only`
+    write_retry: RetryPolicy,
 }
 
 impl AwsPr {
-    pub fn new() -> Self {
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
         Self {
             logger: Logger::new(),
-            zsh_command: ZshCommand::new(),
+            runner,
+            session: AwsSession::new(),
+            retry: RetryPolicy::new(),
+            write_retry: RetryPolicy::throttling_only(),
         }
     }
 
-    pub async fn create(
-        &self,
-        repo: &str,
-        title: Option<&str>,
-        source_branch: Option<&str>,
-        target: &str,
-        abort_early: bool,
-    ) -> CustomResult<String> {
-        self.repo_exists(repo)?;
+    /// Creates a pull request, or updates the open one for the same branches.
+    /// Only `create-pull-request` itself is awaited, the git and AWS calls
+    /// and prompts around it run on the blocking pool.
+    pub async fn create(&self, repo: &str, options: &CreatePrOptions) -> CustomResult<String> {
+        let draft = {
+            let (repo, options) = (repo.to_string(), options.clone());
+            self.blocking(move |pr| pr.draft(&repo, &options)).await?
+        };
+        let (input, approval_rule) = match draft {
+            Draft::Updated(link) => return Ok(link),
+            Draft::New {
+                input,
+                approval_rule,
+            } => (input, approval_rule),
+        };
+
+        let command = Self::codecommit_json("create-pull-request", input);
+
+        let output = self
+            .session
+            .run_async(|| {
+                self.write_retry
+                    .run_async(|| command::execute_async(Arc::clone(&self.runner), command.clone()))
+            })
+            .await?;
+
+        let commit: Commit = serde_json::from_slice(&output.stdout).map_err(|err| {
+            CustomError::Parse(format!(
+                "Failed to parse create-pull-request response: {}",
+                err
+            ))
+        })?;
+
+        let id = commit.pull_request.pull_request_id;
+        let pr_link = Self::console_link(repo, &id);
+        self.logger.debug(format!("Created PR in AWS: {}", repo));
+
+        if let Some((name, content)) = approval_rule {
+            let repo = repo.to_string();
+            self.blocking(move |pr| pr.attach_approval_rule(&repo, &id, &[], &name, &content))
+                .await?;
+        }
+
+        Ok(pr_link)
+    }
+
+    /// Runs the blocking `step` on the blocking pool of tokio, with an
+    /// `AwsPr` of its own sharing the runner.
+    async fn blocking<T, F>(&self, step: F) -> CustomResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&AwsPr) -> CustomResult<T> + Send + 'static,
+    {
+        let runner = Arc::clone(&self.runner);
+        tokio::task::spawn_blocking(move || step(&AwsPr::with_runner(runner)))
+            .await
+            .map_err(|err| CustomError::CommandExecution(format!("Task failed: {}", err)))?
+    }
+
+    /// Everything `create` does before `create-pull-request`: checks the
+    /// branch is pushed and merges, builds the description and offers to
+    /// update an open pull request for the same branches instead.
+    fn draft(&self, repo: &str, options: &CreatePrOptions) -> CustomResult<Draft> {
+        let target = options.target.as_str();
+        let approval_rule = Self::resolve_approval_rule(options)?;
+        if !self.session.run(|| self.repo_exists(repo))? {
+            return Err(CustomError::NotFound(format!(
+                "Repository '{}' does not exist",
+                repo
+            )));
+        }
 
         self.logger.debug(format!("Creating PR in AWS: {}", repo));
 
-        let title = match title {
-            Some(t) => t.to_string(),
+        let title = match options.title {
+            Some(ref t) => t.to_string(),
             None => self.get_commit_message()?,
         };
 
-        let source = match source_branch {
-            Some(s) => s.to_string(),
+        let source = match options.source_branch {
+            Some(ref s) => s.to_string(),
             None => self.get_current_branch()?,
         };
 
-        let has_conflicts = self.has_merge_conflicts(repo, target, &source)?;
+        self.ensure_pushed(repo, &source)?;
 
-        if has_conflicts {
-            self.logger.warn(format!(
-                "\n\n\nSource ({0}) and target ({1}) can't be merged due to conflicts\n\n\n",
-                source, target
-            ));
+        let conflicts = self
+            .session
+            .run(|| self.get_merge_conflicts(repo, target, &source, options.merge_strategy))?;
+
+        if !conflicts.mergeable {
+            self.print_conflicts(&conflicts, &source, target, options.merge_strategy);
 
-            if abort_early {
+            if options.abort_early {
                 self.logger.warn("Aborting due to merge conflicts");
                 return Err(CustomError::CommandExecution(
                     "Aborting due to merge conflicts".to_string(),
@@ -74,72 +446,885 @@ impl AwsPr {
             }
         }
 
-        let command = format!(
-            "aws codecommit create-pull-request --title '{0}' --targets repositoryName={1},sourceReference={2},destinationReference={3}",
-            title,
-            repo,
-            source,
-            target
+        let description_builder = PrDescription::with_runner(Arc::clone(&self.runner));
+        let mut description = match options.description {
+            Some(ref d) => d.to_string(),
+            None => description_builder.build(&title, &source, target)?,
+        };
+        if options.edit {
+            description = description_builder.edit(&description)?;
+        }
+
+        let existing = self.find_open_pull_request(repo, |pr_target| {
+            pr_target.source_reference == format!("refs/heads/{}", source)
+                && pr_target.destination_reference == format!("refs/heads/{}", target)
+        })?;
+        if let Some(existing) = existing {
+            let id = existing.pull_request_id;
+            let update = Confirm::new()
+                .with_prompt(format!(
+                    "Pull request #{} from {} to {} is already open, update it instead?",
+                    id, source, target
+                ))
+                .default(true)
+                .interact()
+                .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
+
+            if !update {
+                return Err(CustomError::Cancelled(format!(
+                    "Pull request #{} is already open for {} -> {}",
+                    id, source, target
+                )));
+            }
+
+            self.edit(&id, Some(&title), Some(&description))?;
+            if let Some((name, content)) = approval_rule {
+                self.attach_approval_rule(repo, &id, &existing.approval_rules, &name, &content)?;
+            }
+            return Ok(Draft::Updated(Self::console_link(repo, &id)));
+        }
+
+        let mut input = json!({
+            "title": title,
+            "targets": [{
+                "repositoryName": repo,
+                "sourceReference": source,
+                "destinationReference": target,
+            }],
+        });
+        if !description.is_empty() {
+            input["description"] = json!(description);
+        }
+        Ok(Draft::New {
+            input,
+            approval_rule,
+        })
+    }
+
+    /// Prints the pull requests of `repo` with the given status, optionally
+    /// only those authored by the current caller.
+    pub fn list(&self, repo: &str, status: PrStatus, mine: bool) -> CustomResult<()> {
+        if !self.session.run(|| self.repo_exists(repo))? {
+            return Err(CustomError::NotFound(format!(
+                "Repository '{}' does not exist",
+                repo
+            )));
+        }
+
+        let author_arn = if mine {
+            Some(
+                self.session
+                    .run(|| AwsSts::with_runner(Arc::clone(&self.runner)).get_caller_identity())?
+                    .arn,
+            )
+        } else {
+            None
+        };
+
+        let ids = self.list_pull_request_ids(repo, status, author_arn.as_deref())?;
+        if ids.is_empty() {
+            println!(
+                "No {} pull requests in {}",
+                status.as_aws().to_lowercase(),
+                repo
+            );
+            return Ok(());
+        }
+
+        for pull_request in self.get_pull_requests(&ids)? {
+            let (source, target) = pull_request
+                .pull_request_targets
+                .first()
+                .map(|target| {
+                    (
+                        Self::short_ref(&target.source_reference),
+                        Self::short_ref(&target.destination_reference),
+                    )
+                })
+                .unwrap_or_default();
+
+            println!(
+                "#{:<6} {:<50} {} -> {}  ({})",
+                pull_request.pull_request_id,
+                pull_request.title,
+                source,
+                target,
+                Self::author_name(&pull_request.author_arn)
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Prints the details of one pull request.
+    pub fn show(&self, repo: &str, id: &str) -> CustomResult<()> {
+        let pull_request = self.session.run(|| self.get_pull_request(id))?;
+        let approvals = self
+            .session
+            .run(|| self.get_approval_states(id, &pull_request.revision_id))?;
+
+        println!("#{} {}", pull_request.pull_request_id, pull_request.title);
+        println!("Status: {}", pull_request.pull_request_status);
+        println!("Author: {}", Self::author_name(&pull_request.author_arn));
+
+        for target in &pull_request.pull_request_targets {
+            let source = Self::short_ref(&target.source_reference);
+            let destination = Self::short_ref(&target.destination_reference);
+            println!(
+                "Branches: {} -> {} ({})",
+                source, destination, target.repository_name
+            );
+
+            let merge_status = match &target.merge_metadata {
+                Some(metadata) if metadata.is_merged => match &metadata.merged_by {
+                    Some(merged_by) => format!("merged by {}", Self::author_name(merged_by)),
+                    None => "merged".to_string(),
+                },
+                _ if pull_request.pull_request_status == "OPEN" => {
+                    let conflicts = self.session.run(|| {
+                        self.get_merge_conflicts(
+                            &target.repository_name,
+                            &destination,
+                            &source,
+                            MergeStrategy::ThreeWay,
+                        )
+                    })?;
+                    if conflicts.mergeable {
+                        "mergeable".to_string()
+                    } else if conflicts.conflict_metadata_list.is_empty() {
+                        "not mergeable".to_string()
+                    } else {
+                        let files: Vec<&str> = conflicts
+                            .conflict_metadata_list
+                            .iter()
+                            .map(|conflict| conflict.file_path.as_str())
+                            .collect();
+                        format!("conflicts in {}", files.join(", "))
+                    }
+                }
+                _ => "not merged".to_string(),
+            };
+            println!("Merge: {}", merge_status);
+        }
+
+        if !pull_request.approval_rules.is_empty() {
+            let evaluation = self
+                .session
+                .run(|| self.evaluate_approval_rules(id, &pull_request.revision_id))?;
+
+            println!("Approval rules:");
+            for rule in &pull_request.approval_rules {
+                let state = if evaluation
+                    .approval_rules_satisfied
+                    .contains(&rule.approval_rule_name)
+                {
+                    "satisfied"
+                } else {
+                    "not satisfied"
+                };
+                println!("  {} {}", rule.approval_rule_name, state);
+            }
+            if evaluation.overridden {
+                println!("  (overridden)");
+            }
+        }
+
+        if approvals.is_empty() {
+            println!("Approvals: none");
+        } else {
+            println!("Approvals:");
+            for approval in &approvals {
+                println!(
+                    "  {} {}",
+                    Self::author_name(&approval.user_arn),
+                    approval.approval_state
+                );
+            }
+        }
+
+        if let Some(description) = pull_request
+            .description
+            .as_deref()
+            .filter(|description| !description.trim().is_empty())
+        {
+            println!("\n{}\n", description.trim());
+        }
+
+        println!(
+            "{}",
+            Self::console_link(repo, &pull_request.pull_request_id)
         );
 
-        let output = self.zsh_command.execute(&command)?;
+        Ok(())
+    }
+
+    /// Merges pull request `id` after checking that it is open, mergeable with
+    /// `strategy` and approved, then optionally deletes its source branch.
+    pub fn merge(
+        &self,
+        repo: &str,
+        id: &str,
+        strategy: MergeStrategy,
+        commit: &MergeCommit,
+        delete_branch: bool,
+    ) -> CustomResult<()> {
+        let pull_request = self.session.run(|| self.get_pull_request(id))?;
+
+        if pull_request.pull_request_status != "OPEN" {
+            return Err(CustomError::CommandExecution(format!(
+                "Pull request {} is {}",
+                id,
+                pull_request.pull_request_status.to_lowercase()
+            )));
+        }
+
+        let target = pull_request.pull_request_targets.first().ok_or_else(|| {
+            CustomError::CommandExecution(format!("Pull request {} has no targets", id))
+        })?;
+        let source = Self::short_ref(&target.source_reference);
+        let destination = Self::short_ref(&target.destination_reference);
+
+        let conflicts = self
+            .session
+            .run(|| self.get_merge_conflicts(repo, &destination, &source, strategy))?;
+        if !conflicts.mergeable {
+            self.print_conflicts(&conflicts, &source, &destination, strategy);
+            return Err(CustomError::CommandExecution(format!(
+                "{} can't be merged into {} with {}",
+                source,
+                destination,
+                strategy.merge_option()
+            )));
+        }
+
+        let evaluation = self
+            .session
+            .run(|| self.evaluate_approval_rules(id, &pull_request.revision_id))?;
+        if !evaluation.approved && !evaluation.overridden {
+            return Err(CustomError::CommandExecution(format!(
+                "Pull request {} does not satisfy its approval rules: {}",
+                id,
+                evaluation.approval_rules_not_satisfied.join(", ")
+            )));
+        }
+
+        self.logger.info(format!(
+            "Merging #{} {} -> {} ({})",
+            id,
+            source,
+            destination,
+            strategy.merge_option()
+        ));
+
+        let mut input = json!({
+            "pullRequestId": id,
+            "repositoryName": repo,
+        });
+        if strategy != MergeStrategy::FastForward {
+            if let Some(ref message) = commit.message {
+                input["commitMessage"] = json!(message);
+            }
+            if let Some(ref author_name) = commit.author_name {
+                input["authorName"] = json!(author_name);
+            }
+            if let Some(ref email) = commit.email {
+                input["email"] = json!(email);
+            }
+        }
+        let command = Self::codecommit_json(strategy.command(), input).args(["--output", "json"]);
+
+        self.session
+            .run(|| self.write_retry.run(|| self.runner.execute(&command)))?;
+        self.logger
+            .info(format!("Merged #{} into {}", id, destination));
+
+        if delete_branch {
+            self.delete_branch(repo, &source)?;
+        }
+
+        Ok(())
+    }
+
+    /// `id` when given, otherwise the open pull request whose source is the
+    /// current branch.
+    pub fn resolve_id(&self, repo: &str, id: Option<&str>) -> CustomResult<String> {
+        if let Some(id) = id {
+            return Ok(id.to_string());
+        }
+
+        let branch = self.get_current_branch()?;
+        let source_reference = format!("refs/heads/{}", branch);
+
+        let pull_request = self
+            .find_open_pull_request(repo, |target| target.source_reference == source_reference)?
+            .ok_or_else(|| {
+                CustomError::NotFound(format!(
+                    "No open pull request from branch '{}' in {}",
+                    branch, repo
+                ))
+            })?;
+
+        self.logger.info(format!(
+            "Using pull request #{} of branch '{}'",
+            pull_request.pull_request_id, branch
+        ));
+
+        Ok(pull_request.pull_request_id)
+    }
+
+    /// First open pull request of `repo` with a target matching `predicate`.
+    fn find_open_pull_request<P>(
+        &self,
+        repo: &str,
+        predicate: P,
+    ) -> CustomResult<Option<PullRequest>>
+    where
+        P: Fn(&PullRequestTarget) -> bool,
+    {
+        let ids = self.list_pull_request_ids(repo, PrStatus::Open, None)?;
+
+        Ok(self
+            .get_pull_requests(&ids)?
+            .into_iter()
+            .find(|pull_request| pull_request.pull_request_targets.iter().any(&predicate)))
+    }
+
+    /// Updates the title and/or description of `id`. With `edit`, the
+    /// description is opened in `$EDITOR` first, starting from the given
+    /// one or the current one.
+    pub fn edit_interactive(
+        &self,
+        id: &str,
+        title: Option<&str>,
+        description: Option<&str>,
+        edit: bool,
+    ) -> CustomResult<()> {
+        if !edit {
+            return self.edit(id, title, description);
+        }
+
+        let current = match description {
+            Some(d) => d.to_string(),
+            None => self
+                .session
+                .run(|| self.get_pull_request(id))?
+                .description
+                .unwrap_or_default(),
+        };
+        let description = PrDescription::with_runner(Arc::clone(&self.runner)).edit(&current)?;
+
+        self.edit(id, title, Some(&description))
+    }
 
-        let str_json = String::from_utf8(output.stdout).expect("Failed to parse stdout");
-        let commit: Commit = serde_json::from_str(&str_json).expect("Failed to parse json");
+    fn edit(&self, id: &str, title: Option<&str>, description: Option<&str>) -> CustomResult<()> {
+        if title.is_none() && description.is_none() {
+            return Err(CustomError::CommandExecution(
+                "Nothing to update, give a title or a description".to_string(),
+            ));
+        }
+
+        if let Some(title) = title {
+            let command = Self::codecommit_json(
+                "update-pull-request-title",
+                json!({ "pullRequestId": id, "title": title }),
+            );
+            self.session
+                .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+            self.logger.info(format!("Updated title of #{}", id));
+        }
+
+        if let Some(description) = description {
+            let command = Self::codecommit_json(
+                "update-pull-request-description",
+                json!({ "pullRequestId": id, "description": description }),
+            );
+            self.session
+                .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+            self.logger.info(format!("Updated description of #{}", id));
+        }
 
-        let pr_link = format!(
+        Ok(())
+    }
+
+    /// Closes `id` without merging it.
+    pub fn close(&self, id: &str) -> CustomResult<()> {
+        let command = Self::codecommit("update-pull-request-status").args([
+            "--pull-request-id",
+            id,
+            "--pull-request-status",
+            "CLOSED",
+        ]);
+        self.session
+            .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+        self.logger.info(format!("Closed #{}", id));
+
+        Ok(())
+    }
+
+    /// Approves or revokes the approval of the current revision of `id`.
+    pub fn review(&self, id: &str, state: ApprovalState) -> CustomResult<()> {
+        let pull_request = self.session.run(|| self.get_pull_request(id))?;
+
+        let command = Self::codecommit("update-pull-request-approval-state").args([
+            "--pull-request-id",
+            id,
+            "--revision-id",
+            &pull_request.revision_id,
+            "--approval-state",
+            state.as_aws(),
+        ]);
+        self.session
+            .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+
+        match state {
+            ApprovalState::Approve => self.logger.info(format!("Approved #{}", id)),
+            ApprovalState::Revoke => self.logger.info(format!("Revoked approval of #{}", id)),
+        }
+
+        Ok(())
+    }
+
+    /// Posts `content` on `id`, on a file and line of the source revision
+    /// when `location` is given.
+    pub fn comment(
+        &self,
+        repo: &str,
+        id: &str,
+        content: &str,
+        location: Option<&CommentLocation>,
+    ) -> CustomResult<()> {
+        let pull_request = self.session.run(|| self.get_pull_request(id))?;
+        let target = pull_request.pull_request_targets.first().ok_or_else(|| {
+            CustomError::CommandExecution(format!("Pull request {} has no targets", id))
+        })?;
+
+        let mut input = json!({
+            "pullRequestId": id,
+            "repositoryName": repo,
+            "beforeCommitId": target.destination_commit,
+            "afterCommitId": target.source_commit,
+            "content": content,
+        });
+        if let Some(location) = location {
+            input["location"] = json!({
+                "filePath": location.file_path,
+                "relativeFileVersion": "AFTER",
+            });
+            if let Some(line) = location.line {
+                input["location"]["filePosition"] = json!(line);
+            }
+        }
+        let command = Self::codecommit_json("post-comment-for-pull-request", input);
+
+        self.session
+            .run(|| self.write_retry.run(|| self.runner.execute(&command)))?;
+        self.logger.info(format!("Commented on #{}", id));
+
+        Ok(())
+    }
+
+    /// Rule name and content from `--approval-rule` or `--approval-rule-json`,
+    /// checked before anything is created.
+    fn resolve_approval_rule(options: &CreatePrOptions) -> CustomResult<Option<(String, String)>> {
+        if let Some(ref json) = options.approval_rule_json {
+            serde_json::from_str::<serde_json::Value>(json).map_err(|err| {
+                CustomError::Config(format!("Invalid approval rule JSON: {}", err))
+            })?;
+            return Ok(Some((INLINE_APPROVAL_RULE_NAME.to_string(), json.clone())));
+        }
+
+        let Some(ref name) = options.approval_rule else {
+            return Ok(None);
+        };
+
+        let config = AppConfig::load();
+        let rule = config.pr.approval_rules.get(name).ok_or_else(|| {
+            CustomError::Config(format!(
+                "No approval rule '{}' in [pr.approval_rules] of the config file",
+                name
+            ))
+        })?;
+        let content = serde_json::to_string(&ApprovalRuleContent::from(rule)).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to encode approval rule: {}", err))
+        })?;
+
+        Ok(Some((name.clone(), content)))
+    }
+
+    /// Adds the rule, or replaces its content when the pull request already
+    /// has a rule of that name. The pull request exists either way, so its
+    /// link is logged when this fails.
+    fn attach_approval_rule(
+        &self,
+        repo: &str,
+        id: &str,
+        attached: &[ApprovalRule],
+        name: &str,
+        content: &str,
+    ) -> CustomResult<()> {
+        let result = if attached.iter().any(|rule| rule.approval_rule_name == name) {
+            self.update_approval_rule(id, name, content)
+        } else {
+            self.create_approval_rule(id, name, content)
+        };
+
+        if result.is_err() {
+            self.logger.error(format!(
+                "Pull request #{} is missing approval rule '{}': {}",
+                id,
+                name,
+                Self::console_link(repo, id)
+            ));
+        }
+        result
+    }
+
+    fn update_approval_rule(&self, id: &str, name: &str, content: &str) -> CustomResult<()> {
+        self.logger
+            .info(format!("Updating approval rule '{}' of #{}", name, id));
+        let command = Self::codecommit("update-pull-request-approval-rule-content").args([
+            "--pull-request-id",
+            id,
+            "--approval-rule-name",
+            name,
+            "--new-rule-content",
+            content,
+        ]);
+        self.session
+            .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+        self.logger
+            .info(format!("Updated approval rule '{}'", name));
+
+        Ok(())
+    }
+
+    fn create_approval_rule(&self, id: &str, name: &str, content: &str) -> CustomResult<()> {
+        self.logger
+            .info(format!("Adding approval rule '{}' to #{}", name, id));
+        let command = Self::codecommit("create-pull-request-approval-rule").args([
+            "--pull-request-id",
+            id,
+            "--approval-rule-name",
+            name,
+            "--approval-rule-content",
+            content,
+        ]);
+        self.session
+            .run(|| self.write_retry.run(|| self.runner.execute(&command)))?;
+        self.logger.info(format!("Added approval rule '{}'", name));
+
+        Ok(())
+    }
+
+    fn evaluate_approval_rules(&self, id: &str, revision_id: &str) -> CustomResult<Evaluation> {
+        let command = Self::codecommit("evaluate-pull-request-approval-rules").args([
+            "--pull-request-id",
+            id,
+            "--revision-id",
+            revision_id,
+            "--output",
+            "json",
+        ]);
+        let output = self.retry.run(|| self.runner.execute(&command))?;
+
+        let response: ApprovalRulesEvaluation =
+            serde_json::from_slice(&output.stdout).map_err(|err| {
+                CustomError::Parse(format!(
+                    "Failed to parse approval rules evaluation: {}",
+                    err
+                ))
+            })?;
+
+        Ok(response.evaluation)
+    }
+
+    fn delete_branch(&self, repo: &str, branch: &str) -> CustomResult<()> {
+        self.logger
+            .info(format!("Deleting branch '{}' of {}", branch, repo));
+        let command = Self::codecommit("delete-branch").args([
+            "--repository-name",
+            repo,
+            "--branch-name",
+            branch,
+        ]);
+        self.session
+            .run(|| self.write_retry.run(|| self.runner.execute(&command)))?;
+        self.logger.info(format!("Deleted branch '{}'", branch));
+
+        Ok(())
+    }
+
+    fn codecommit(operation: &str) -> CommandLine {
+        CommandLine::new("aws").args(["codecommit", operation])
+    }
+
+    /// `aws codecommit <operation>` with its parameters read from stdin. As
+    /// arguments, values like a title starting with `file://` would be
+    /// replaced by the contents of that file by the AWS CLI.
+    fn codecommit_json(operation: &str, input: serde_json::Value) -> CommandLine {
+        Self::codecommit(operation)
+            .args(["--cli-input-json", "file:///dev/stdin"])
+            .stdin(input.to_string())
+    }
+
+    fn console_link(repo: &str, id: &str) -> String {
+        format!(
             "https://console.aws.amazon.com/codesuite/codecommit/repositories/{}/pull-requests/{}/details?region=us-east-1",
             repo,
-            commit.pull_request.pull_request_id
-        );
-        self.logger.debug(format!("Created PR in AWS: {}", repo));
+            id
+        )
+    }
 
-        Ok(pr_link)
+    /// `refs/heads/main` -> `main`
+    fn short_ref(reference: &str) -> String {
+        reference
+            .strip_prefix("refs/heads/")
+            .unwrap_or(reference)
+            .to_string()
+    }
+
+    /// Session or user name at the end of an IAM or STS ARN.
+    fn author_name(arn: &str) -> &str {
+        arn.rsplit('/').next().unwrap_or(arn)
     }
 
-    fn has_merge_conflicts(
+    fn list_pull_request_ids(
+        &self,
+        repo: &str,
+        status: PrStatus,
+        author_arn: Option<&str>,
+    ) -> CustomResult<Vec<String>> {
+        self.logger
+            .debug(format!("Listing pull requests of '{}'", repo));
+
+        let mut ids = Vec::new();
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut command = Self::codecommit("list-pull-requests").args([
+                "--repository-name",
+                repo,
+                "--pull-request-status",
+                status.as_aws(),
+                "--output",
+                "json",
+            ]);
+            if let Some(author_arn) = author_arn {
+                command = command.arg("--author-arn").arg(author_arn);
+            }
+            if let Some(ref token) = next_token {
+                command = command.arg("--next-token").arg(token);
+            }
+
+            let output = self
+                .session
+                .run(|| self.retry.run(|| self.runner.execute(&command)))?;
+            let page: PullRequestIds = serde_json::from_slice(&output.stdout).map_err(|err| {
+                CustomError::Parse(format!(
+                    "Failed to parse list-pull-requests response: {}",
+                    err
+                ))
+            })?;
+
+            ids.extend(page.pull_request_ids);
+            next_token = page.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        self.logger
+            .debug(format!("Listed {} pull requests", ids.len()));
+
+        Ok(ids)
+    }
+
+    /// CodeCommit has no batch read for pull requests, so they are fetched
+    /// `FETCH_BATCH_SIZE` at a time in parallel, keeping the order of `ids`.
+    fn get_pull_requests(&self, ids: &[String]) -> CustomResult<Vec<PullRequest>> {
+        let mut pull_requests = Vec::with_capacity(ids.len());
+
+        // One session check per batch, expired credentials would otherwise
+        // be refreshed by every thread at once
+        for batch in ids.chunks(FETCH_BATCH_SIZE) {
+            pull_requests.extend(self.session.run(|| self.get_pull_request_batch(batch))?);
+        }
+
+        Ok(pull_requests)
+    }
+
+    fn get_pull_request_batch(&self, ids: &[String]) -> CustomResult<Vec<PullRequest>> {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = ids
+                .iter()
+                .map(|id| scope.spawn(move || self.get_pull_request(id)))
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().unwrap_or_else(|_| {
+                        Err(CustomError::CommandExecution(
+                            "Pull request fetch panicked".to_string(),
+                        ))
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn get_pull_request(&self, id: &str) -> CustomResult<PullRequest> {
+        self.logger.debug(format!("Getting pull request {}", id));
+
+        let command = Self::codecommit("get-pull-request").args([
+            "--pull-request-id",
+            id,
+            "--output",
+            "json",
+        ]);
+        let output = self.retry.run(|| self.runner.execute(&command))?;
+
+        let response: Commit = serde_json::from_slice(&output.stdout).map_err(|err| {
+            CustomError::Parse(format!(
+                "Failed to parse get-pull-request response: {}",
+                err
+            ))
+        })?;
+
+        Ok(response.pull_request)
+    }
+
+    fn get_approval_states(&self, id: &str, revision_id: &str) -> CustomResult<Vec<Approval>> {
+        let command = Self::codecommit("get-pull-request-approval-states").args([
+            "--pull-request-id",
+            id,
+            "--revision-id",
+            revision_id,
+            "--output",
+            "json",
+        ]);
+        let output = self.retry.run(|| self.runner.execute(&command))?;
+
+        let response: ApprovalStates = serde_json::from_slice(&output.stdout).map_err(|err| {
+            CustomError::Parse(format!("Failed to parse approval states response: {}", err))
+        })?;
+
+        Ok(response.approvals)
+    }
+
+    /// Whether `source_branch` merges into `target_branch` with `strategy`,
+    /// along with every conflicting file across all response pages.
+    fn get_merge_conflicts(
         &self,
         repo_name: &str,
         target_branch: &str,
         source_branch: &str,
-    ) -> CustomResult<bool> {
+        strategy: MergeStrategy,
+    ) -> CustomResult<PullRequestMergeConflicts> {
         self.logger.debug("Checking for merge conflicts");
 
-        let command = format!(
-            "aws codecommit get-merge-conflicts --repository-name {0} --destination-commit {1} --source-commit-specifier {2} --merge-option FAST_FORWARD_MERGE", repo_name, target_branch, source_branch
-        );
+        let mut report: Option<PullRequestMergeConflicts> = None;
+        let mut next_token: Option<String> = None;
 
-        let output = self.zsh_command.execute(&command)?;
+        loop {
+            let mut command = Self::codecommit("get-merge-conflicts").args([
+                "--repository-name",
+                repo_name,
+                "--destination-commit",
+                target_branch,
+                "--source-commit-specifier",
+                source_branch,
+                "--merge-option",
+                strategy.merge_option(),
+            ]);
+            if let Some(ref token) = next_token {
+                command = command.arg("--next-token").arg(token);
+            }
 
-        let conflicts = String::from_utf8(output.stdout)
-            .map_err(|err| CustomError::CommandExecution(err.to_string()))?;
+            let output = self.retry.run(|| self.runner.execute(&command))?;
 
-        let conflicts_output: PullRequestMergeConflicts = match serde_json::from_str(&conflicts) {
-            Ok(c) => c,
-            Err(e) => {
-                self.logger.error(format!(
-                    "Can't parse merge conflicts output {:?}: {:?}",
-                    e, conflicts
-                ));
+            let conflicts = String::from_utf8(output.stdout)
+                .map_err(|err| CustomError::CommandExecution(err.to_string()))?;
 
-                return Err(CustomError::CommandExecution(
-                    "Failed to parse merge conflicts".to_string(),
-                ));
+            let page: PullRequestMergeConflicts = match serde_json::from_str(&conflicts) {
+                Ok(c) => c,
+                Err(e) => {
+                    self.logger.error(format!(
+                        "Can't parse merge conflicts output {:?}: {:?}",
+                        e, conflicts
+                    ));
+
+                    return Err(CustomError::Parse(
+                        "Failed to parse merge conflicts".to_string(),
+                    ));
+                }
+            };
+
+            next_token = page.next_token.clone();
+            match report {
+                Some(ref mut report) => report
+                    .conflict_metadata_list
+                    .extend(page.conflict_metadata_list),
+                None => report = Some(page),
             }
-        };
+
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        let mut report = report
+            .ok_or_else(|| CustomError::Parse("Empty merge conflicts response".to_string()))?;
+        report.next_token = None;
 
         self.logger.debug(format!(
-            "Checked merge conflicts: {}",
-            conflicts_output.mergeable
+            "Checked merge conflicts: {} ({} files)",
+            report.mergeable,
+            report.conflict_metadata_list.len()
         ));
 
-        Ok(!conflicts_output.mergeable)
+        Ok(report)
+    }
+
+    /// Prints why `source` can't be merged into `target`.
+    fn print_conflicts(
+        &self,
+        report: &PullRequestMergeConflicts,
+        source: &str,
+        target: &str,
+        strategy: MergeStrategy,
+    ) {
+        if report.conflict_metadata_list.is_empty() {
+            println!(
+                "{} can't be merged into {} with {}",
+                source,
+                target,
+                strategy.merge_option()
+            );
+            if strategy == MergeStrategy::FastForward {
+                println!("  the branches have diverged, try a squash or three-way merge");
+            }
+            return;
+        }
+
+        println!(
+            "{} conflicting file(s) merging {} into {} with {}:",
+            report.conflict_metadata_list.len(),
+            source,
+            target,
+            strategy.merge_option()
+        );
+        for conflict in &report.conflict_metadata_list {
+            println!("  {}: {}", conflict.file_path, conflict.describe());
+        }
     }
 
     fn get_commit_message(&self) -> CustomResult<String> {
         self.logger.debug("Getting commit message");
 
-        let output = self.zsh_command.execute("git log -1 --pretty=%B")?;
+        let output =
+            self.runner
+                .execute(&CommandLine::new("git").args(["log", "-1", "--pretty=%B"]))?;
 
         let commit_message = String::from_utf8(output.stdout)
             .map_err(|err| CustomError::CommandExecution(err.to_string()))?;
@@ -149,10 +1334,123 @@ impl AwsPr {
         Ok(commit_message.trim().to_string())
     }
 
+    /// Makes sure the remote has every commit of `branch`, offering to push
+    /// it when it is missing or behind. Refuses to go on with uncommitted
+    /// changes of a checked out `branch`, since they would silently be left
+    /// out of the pull request.
+    fn ensure_pushed(&self, repo: &str, branch: &str) -> CustomResult<()> {
+        if self.get_current_branch()? == branch {
+            let status = self.runner.execute(&CommandLine::new("git").args([
+                "status",
+                "--porcelain",
+                "--untracked-files=no",
+            ]))?;
+            if !String::from_utf8_lossy(&status.stdout).trim().is_empty() {
+                return Err(CustomError::CommandExecution(
+                    "Working tree has uncommitted changes, commit or stash them before creating a pull request"
+                        .to_string(),
+                ));
+            }
+        }
+
+        let output = self.runner.execute(&CommandLine::new("git").args([
+            "for-each-ref",
+            "--format=%(upstream:remotename)%09%(upstream:short)",
+            &format!("refs/heads/{}", branch),
+        ]))?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let (remote, upstream) = output.trim().split_once('\t').unwrap_or_default();
+        let upstream = Some(upstream.to_string()).filter(|upstream| !upstream.is_empty());
+        let remote = match remote {
+            "" => self.codecommit_remote(repo)?,
+            remote => remote.to_string(),
+        };
+
+        let prompt = match upstream {
+            None => format!("Branch '{}' is not on the remote, push it?", branch),
+            Some(ref upstream) => {
+                let output = self.runner.execute(&CommandLine::new("git").args([
+                    "rev-list",
+                    "--count",
+                    &format!("{}..{}", upstream, branch),
+                    "--",
+                ]))?;
+                let ahead: u32 = String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .parse()
+                    .unwrap_or_default();
+                if ahead == 0 {
+                    return Ok(());
+                }
+                format!(
+                    "Branch '{}' is {} commit(s) ahead of '{}', push it?",
+                    branch, ahead, upstream
+                )
+            }
+        };
+
+        let push = Confirm::new()
+            .with_prompt(prompt)
+            .default(true)
+            .interact()
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?;
+        if !push {
+            return Err(CustomError::Cancelled(format!(
+                "Branch '{}' must be pushed before creating a pull request",
+                branch
+            )));
+        }
+
+        self.logger.info(format!("Pushing branch '{}'", branch));
+        // May ask for credentials, and uploading takes a while on slow links
+        let command = CommandLine::new("git")
+            .args(["push", "-u", &remote, "--", branch])
+            .interactive()
+            .timeout(Duration::from_secs(30 * 60));
+        self.runner.execute(&command)?;
+        self.logger.info(format!("Pushed branch '{}'", branch));
+
+        Ok(())
+    }
+
+    /// The git remote pointing at `repo` in CodeCommit, over HTTPS, SSH or
+    /// git-remote-codecommit.
+    fn codecommit_remote(&self, repo: &str) -> CustomResult<String> {
+        let output = self
+            .runner
+            .execute(&CommandLine::new("git").args(["remote", "-v"]))?;
+        let output = String::from_utf8_lossy(&output.stdout);
+
+        output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                Some((fields.next()?, fields.next()?))
+            })
+            .find(|(_, url)| {
+                let url = url.trim_end_matches('/');
+                if url.starts_with("codecommit:") {
+                    // codecommit::<region>://[<profile>@]<repo>
+                    url.rsplit(['/', '@']).next() == Some(repo)
+                } else {
+                    url.contains("git-codecommit.") && url.ends_with(&format!("/v1/repos/{}", repo))
+                }
+            })
+            .map(|(name, _)| name.to_string())
+            .ok_or_else(|| {
+                CustomError::Config(format!(
+                    "No git remote points at the CodeCommit repository '{}'",
+                    repo
+                ))
+            })
+    }
+
     fn get_current_branch(&self) -> CustomResult<String> {
         self.logger.debug("Getting current branch");
 
-        let output = self.zsh_command.execute("git branch --show-current")?;
+        let output = self
+            .runner
+            .execute(&CommandLine::new("git").args(["branch", "--show-current"]))?;
 
         let commit_message = String::from_utf8(output.stdout)
             .map_err(|err| CustomError::CommandExecution(err.to_string()))?;
@@ -165,11 +1463,8 @@ impl AwsPr {
     fn repo_exists(&self, repo_name: &str) -> CustomResult<bool> {
         self.logger
             .debug(format!("Checking if repository '{}' exists", repo_name));
-        let command = format!(
-            "aws codecommit get-repository --repository-name {}",
-            repo_name
-        );
-        let output = self.zsh_command.execute(&command);
+        let command = Self::codecommit("get-repository").args(["--repository-name", repo_name]);
+        let output = self.retry.run(|| self.runner.execute(&command));
 
         match output {
             Ok(_) => {
@@ -193,3 +1488,190 @@ impl AwsPr {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::FakeRunner;
+
+    const REPO: &str = "service";
+
+    fn options() -> CreatePrOptions {
+        CreatePrOptions {
+            title: Some("Add retries".to_string()),
+            source_branch: Some("feature".to_string()),
+            target: "main".to_string(),
+            merge_strategy: MergeStrategy::ThreeWay,
+            description: Some("Retries throttled calls".to_string()),
+            edit: false,
+            abort_early: true,
+            approval_rule: None,
+            approval_rule_json: None,
+        }
+    }
+
+    fn current_branch(branch: &str) -> FakeRunner {
+        FakeRunner::new()
+            .expect(
+                AwsPr::codecommit("get-repository").args(["--repository-name", REPO]),
+                "{}",
+            )
+            .expect(
+                CommandLine::new("git").args(["branch", "--show-current"]),
+                &format!("{}\n", branch),
+            )
+    }
+
+    /// The commands leading up to `create-pull-request` for a branch that is
+    /// already pushed and merges cleanly. The working tree is only checked
+    /// when the branch is checked out.
+    fn pushed_branch(current: &str) -> FakeRunner {
+        let mut runner = current_branch(current);
+        if current == "feature" {
+            runner = runner.expect(
+                CommandLine::new("git").args(["status", "--porcelain", "--untracked-files=no"]),
+                "",
+            );
+        }
+
+        runner
+            .expect(
+                CommandLine::new("git").args([
+                    "for-each-ref",
+                    "--format=%(upstream:remotename)%09%(upstream:short)",
+                    "refs/heads/feature",
+                ]),
+                "origin\torigin/feature\n",
+            )
+            .expect(
+                CommandLine::new("git").args([
+                    "rev-list",
+                    "--count",
+                    "origin/feature..feature",
+                    "--",
+                ]),
+                "0\n",
+            )
+            .expect(
+                AwsPr::codecommit("get-merge-conflicts").args([
+                    "--repository-name",
+                    REPO,
+                    "--destination-commit",
+                    "main",
+                    "--source-commit-specifier",
+                    "feature",
+                    "--merge-option",
+                    "THREE_WAY_MERGE",
+                ]),
+                r#"{"mergeable": true, "conflictMetadataList": []}"#,
+            )
+            .expect(
+                AwsPr::codecommit("list-pull-requests").args([
+                    "--repository-name",
+                    REPO,
+                    "--pull-request-status",
+                    "OPEN",
+                    "--output",
+                    "json",
+                ]),
+                r#"{"pullRequestIds": []}"#,
+            )
+    }
+
+    /// `create-pull-request` with the input built from `options()`.
+    fn create_pull_request() -> CommandLine {
+        AwsPr::codecommit_json(
+            "create-pull-request",
+            json!({
+                "title": "Add retries",
+                "description": "Retries throttled calls",
+                "targets": [{
+                    "repositoryName": REPO,
+                    "sourceReference": "feature",
+                    "destinationReference": "main",
+                }],
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn creates_pull_request_for_pushed_branch() {
+        let runner = Arc::new(pushed_branch("feature").expect(
+            create_pull_request(),
+            r#"{"pullRequest": {"pullRequestId": "42"}}"#,
+        ));
+
+        let link = AwsPr::with_runner(runner.clone())
+            .create(REPO, &options())
+            .await
+            .unwrap();
+
+        assert_eq!(link, AwsPr::console_link(REPO, "42"));
+        runner.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn attaches_approval_rule_to_new_pull_request() {
+        let content = r#"{"Version": "2018-11-08", "Statements": []}"#;
+        let runner = Arc::new(
+            pushed_branch("feature")
+                .expect(
+                    create_pull_request(),
+                    r#"{"pullRequest": {"pullRequestId": "42"}}"#,
+                )
+                .expect(
+                    AwsPr::codecommit("create-pull-request-approval-rule").args([
+                        "--pull-request-id",
+                        "42",
+                        "--approval-rule-name",
+                        INLINE_APPROVAL_RULE_NAME,
+                        "--approval-rule-content",
+                        content,
+                    ]),
+                    "{}",
+                ),
+        );
+        let options = CreatePrOptions {
+            approval_rule_json: Some(content.to_string()),
+            ..options()
+        };
+
+        AwsPr::with_runner(runner.clone())
+            .create(REPO, &options)
+            .await
+            .unwrap();
+
+        runner.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_uncommitted_changes() {
+        let runner = Arc::new(current_branch("feature").expect(
+            CommandLine::new("git").args(["status", "--porcelain", "--untracked-files=no"]),
+            " M src/main.rs\n",
+        ));
+
+        let result = AwsPr::with_runner(runner.clone())
+            .create(REPO, &options())
+            .await;
+
+        assert!(matches!(result, Err(CustomError::CommandExecution(_))));
+        runner.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn ignores_working_tree_of_other_branch() {
+        // Checking the working tree would be an unexpected command
+        let runner = Arc::new(pushed_branch("main").expect(
+            create_pull_request(),
+            r#"{"pullRequest": {"pullRequestId": "43"}}"#,
+        ));
+
+        AwsPr::with_runner(runner.clone())
+            .create(REPO, &options())
+            .await
+            .unwrap();
+
+        runner.verify().unwrap();
+    }
+}
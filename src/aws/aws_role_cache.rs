@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    aws::aws_sso::RoleCredentials,
+    custom_error::{CustomError, CustomResult},
+    logger::Logger,
+    secret_store::{self, SecretStore},
+};
+
+const CACHE_ENTRY: &str = "role-credentials";
+
+/// Cached credentials are not handed out once they expire within this margin.
+const REFRESH_MARGIN: Duration = Duration::minutes(5);
+
+/// Cache of role credentials, kept in the configured secret store.
+pub struct RoleCredentialCache {
+    logger: Logger,
+    store: Box<dyn SecretStore>,
+}
+
+impl RoleCredentialCache {
+    pub fn new() -> CustomResult<Self> {
+        Ok(Self {
+            logger: Logger::new(),
+            store: secret_store::open()?,
+        })
+    }
+
+    pub fn key(start_url: &str, account_id: &str, role_name: &str) -> String {
+        format!("{}|{}|{}", start_url, account_id, role_name)
+    }
+
+    /// Returns the cached credentials for `key` unless they are about to expire.
+    pub fn get(&self, key: &str) -> Option<RoleCredentials> {
+        let creds = self.read_entries().remove(key)?;
+        let expires_at = creds.expires_at().ok()?;
+
+        if expires_at - REFRESH_MARGIN <= Utc::now() {
+            self.logger.debug(format!(
+                "Cached credentials for '{}' are about to expire",
+                key
+            ));
+            return None;
+        }
+
+        self.logger.debug(format!(
+            "Reusing cached credentials for '{}' valid until {}",
+            key, expires_at
+        ));
+        Some(creds)
+    }
+
+    /// Stores `creds` under `key` and drops every entry that already expired.
+    pub fn put(&self, key: &str, creds: &RoleCredentials) -> CustomResult<()> {
+        let mut entries = self.read_entries();
+        let now = Utc::now();
+        entries.retain(|_, cached| cached.expires_at().is_ok_and(|at| at > now));
+        entries.insert(key.to_string(), creds.clone());
+
+        let json = serde_json::to_string_pretty(&entries).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to serialize credential cache: {}", err))
+        })?;
+
+        self.store.set(CACHE_ENTRY, &json)?;
+        self.logger
+            .debug(format!("Cached credentials for '{}'", key));
+
+        Ok(())
+    }
+
+    /// Removes every cached credential.
+    pub fn clear(&self) -> CustomResult<()> {
+        self.store.delete(CACHE_ENTRY)
+    }
+
+    /// Lists the cache keys with the expiration of their credentials.
+    pub fn entries(&self) -> Vec<(String, DateTime<Utc>)> {
+        let mut entries: Vec<(String, DateTime<Utc>)> = self
+            .read_entries()
+            .into_iter()
+            .filter_map(|(key, creds)| Some((key, creds.expires_at().ok()?)))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// An unreadable or corrupt cache is treated as empty, it only costs a
+    /// round trip to AWS.
+    fn read_entries(&self) -> HashMap<String, RoleCredentials> {
+        let contents = match self.store.get(CACHE_ENTRY) {
+            Ok(Some(contents)) => contents,
+            Ok(None) => return HashMap::new(),
+            Err(err) => {
+                self.logger
+                    .warn(format!("Ignoring unreadable credential cache: {}", err));
+                return HashMap::new();
+            }
+        };
+
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            self.logger
+                .warn(format!("Ignoring unparsable credential cache: {}", err));
+            HashMap::new()
+        })
+    }
+}
@@ -0,0 +1,149 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    config::AppConfig,
+    custom_error::{CustomError, CustomResult},
+    logger::Logger,
+};
+
+type Reauthenticate = Arc<dyn Fn() -> CustomResult<DateTime<Utc>> + Send + Sync>;
+
+/// Credentials most recently exported to the environment of this process.
+struct ActiveSession {
+    expires_at: DateTime<Utc>,
+    /// Fetches fresh credentials into the environment and returns their expiration
+    reauthenticate: Reauthenticate,
+}
+
+static ACTIVE_SESSION: Mutex<Option<ActiveSession>> = Mutex::new(None);
+
+/// Guards AWS calls against credentials that expire while a long operation runs.
+pub struct AwsSession {
+    logger: Logger,
+    warning_margin: Duration,
+}
+
+impl AwsSession {
+    pub fn new() -> Self {
+        Self {
+            logger: Logger::new(),
+            warning_margin: Duration::minutes(AppConfig::load().auth.expiry_warning_minutes),
+        }
+    }
+
+    /// Records the credentials just exported to the environment, and how to
+    /// replace them once they expire.
+    pub fn track<F>(expires_at: DateTime<Utc>, reauthenticate: F)
+    where
+        F: Fn() -> CustomResult<DateTime<Utc>> + Send + Sync + 'static,
+    {
+        if let Ok(mut session) = ACTIVE_SESSION.lock() {
+            *session = Some(ActiveSession {
+                expires_at,
+                reauthenticate: Arc::new(reauthenticate),
+            });
+        }
+    }
+
+    /// Runs one step of an operation. Warns when the tracked credentials are
+    /// about to expire, and when the step fails because they expired or were
+    /// rejected, re-authenticates and retries the step once.
+    pub fn run<T, F>(&self, step: F) -> CustomResult<T>
+    where
+        F: Fn() -> CustomResult<T>,
+    {
+        self.warn_if_expiring();
+
+        match step() {
+            Err(err) if Self::needs_reauthentication(&err) => {
+                let Some(reauthenticate) = Self::reauthenticator() else {
+                    return Err(err);
+                };
+
+                self.logger
+                    .warn("AWS credentials expired, re-authenticating and retrying");
+                let expires_at = reauthenticate()?;
+                if let Ok(mut session) = ACTIVE_SESSION.lock() {
+                    if let Some(session) = session.as_mut() {
+                        session.expires_at = expires_at;
+                    }
+                }
+
+                step()
+            }
+            result => result,
+        }
+    }
+
+    /// `run` for async steps. Re-authenticating runs on the blocking pool.
+    pub async fn run_async<T, F, Fut>(&self, step: F) -> CustomResult<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = CustomResult<T>>,
+    {
+        self.warn_if_expiring();
+
+        match step().await {
+            Err(err) if Self::needs_reauthentication(&err) => {
+                let Some(reauthenticate) = Self::reauthenticator() else {
+                    return Err(err);
+                };
+
+                self.logger
+                    .warn("AWS credentials expired, re-authenticating and retrying");
+                let expires_at = tokio::task::spawn_blocking(move || reauthenticate())
+                    .await
+                    .map_err(|err| {
+                        CustomError::CommandExecution(format!("Re-authentication failed: {}", err))
+                    })??;
+                if let Ok(mut session) = ACTIVE_SESSION.lock() {
+                    if let Some(session) = session.as_mut() {
+                        session.expires_at = expires_at;
+                    }
+                }
+
+                step().await
+            }
+            result => result,
+        }
+    }
+
+    fn warn_if_expiring(&self) {
+        let expires_at = match ACTIVE_SESSION.lock() {
+            Ok(session) => match session.as_ref() {
+                Some(session) => session.expires_at,
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let remaining = expires_at - Utc::now();
+        if remaining <= Duration::zero() {
+            self.logger.warn("AWS credentials have expired");
+        } else if remaining <= self.warning_margin {
+            self.logger.warn(format!(
+                "AWS credentials expire in {} minute(s), at {}",
+                remaining.num_minutes(),
+                expires_at.to_rfc3339()
+            ));
+        }
+    }
+
+    /// Cloned out of the lock so it isn't held while re-authenticating, which
+    /// may wait for a browser login. The caller records the new expiration.
+    fn reauthenticator() -> Option<Reauthenticate> {
+        let session = ACTIVE_SESSION.lock().ok()?;
+        session
+            .as_ref()
+            .map(|session| Arc::clone(&session.reauthenticate))
+    }
+
+    /// `CustomError::from_stderr` classifies expired and rejected tokens as
+    /// `Auth`, fresh credentials are the remedy for all of them.
+    fn needs_reauthentication(err: &CustomError) -> bool {
+        matches!(err, CustomError::Auth(_))
+    }
+}
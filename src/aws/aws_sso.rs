@@ -1,15 +1,24 @@
 use crate::{
+    aws::{
+        aws_credentials_file::CredentialsFile,
+        aws_role_cache::RoleCredentialCache,
+        aws_session::AwsSession,
+        aws_sts::{AssumeRoleOptions, AwsSts},
+    },
+    command::{self, CommandLine, CommandRunner, RetryPolicy},
     constants,
     custom_error::{CustomError, CustomResult},
     logger::Logger,
-    zsh_command::ZshCommand,
 };
 use anyhow::Context;
 use chrono::{DateTime, Utc};
+use dialoguer::Input;
 use dirs::home_dir;
 use glob::glob;
-use serde::Deserialize;
-use std::{env, fs};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fs, path::PathBuf};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -18,9 +27,37 @@ struct SsoCacheEntry {
     expires_at: Option<DateTime<Utc>>,
 }
 
+/// An SSO access token cached by the AWS CLI.
 #[derive(Debug)]
+pub struct SsoToken {
+    pub path: PathBuf,
+    pub start_url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
 struct SsoInput {
     profile: String,
+    /// Role resolved up front, e.g. by account discovery, instead of being
+    /// read from the profile in ~/.aws/config
+    role: Option<ProfileInfo>,
+}
+
+/// How the credentials of a profile are obtained.
+#[derive(Debug)]
+enum ProfileKind {
+    /// `sso_account_id`/`sso_role_name` resolved with the SSO access token
+    Sso,
+    /// `role_arn` assumed with the credentials of `source_profile`
+    Chained {
+        source_profile: String,
+        options: AssumeRoleOptions,
+    },
+    /// IAM user keys from ~/.aws/credentials, optionally guarded by MFA
+    Static {
+        mfa_serial: Option<String>,
+        duration_seconds: Option<u32>,
+    },
 }
 
 #[derive(Deserialize)]
@@ -29,21 +66,35 @@ struct CacheFile {
     access_token: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ProfileInfo {
     account_id: String,
     role_name: String,
     region: String,
+    start_url: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct RoleCredentials {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoleCredentials {
     #[serde(rename = "accessKeyId")]
-    access_key_id: String,
+    pub access_key_id: String,
     #[serde(rename = "secretAccessKey")]
-    secret_access_key: String,
+    pub secret_access_key: String,
     #[serde(rename = "sessionToken")]
-    session_token: String,
+    pub session_token: String,
+    /// Expiration as milliseconds since the Unix epoch
+    pub expiration: i64,
+}
+
+impl RoleCredentials {
+    pub fn expires_at(&self) -> CustomResult<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(self.expiration).ok_or_else(|| {
+            CustomError::Parse(format!(
+                "Invalid credentials expiration: {}",
+                self.expiration
+            ))
+        })
+    }
 }
 
 #[derive(Deserialize)]
@@ -54,20 +105,69 @@ struct SsoResponse {
 
 pub struct AwsSso {
     input: SsoInput,
+    /// Role assumed on top of the profile credentials, e.g. via `--assume-role`
+    assume_role: Option<AssumeRoleOptions>,
+    /// Skips cached credentials, e.g. after AWS rejected them as expired
+    refresh: bool,
     logger: Logger,
-    zsh_command: ZshCommand,
+    runner: Arc<dyn CommandRunner>,
+    retry: RetryPolicy,
+    /// Profiles whose `source_profile` led here, to detect cycles
+    chain: Vec<String>,
 }
 
 impl AwsSso {
     pub fn new(profile: String) -> Self {
         Self {
-            input: SsoInput { profile },
+            input: SsoInput {
+                profile,
+                role: None,
+            },
+            assume_role: None,
+            refresh: false,
             logger: Logger::new(),
-            zsh_command: ZshCommand::new(),
+            runner: command::default_runner(),
+            retry: RetryPolicy::new(),
+            chain: Vec::new(),
         }
     }
 
+    /// Targets an account/role directly, without a matching profile in
+    /// ~/.aws/config.
+    pub fn for_role(account_id: &str, role_name: &str, region: &str) -> Self {
+        let mut sso = Self::new(format!("{}/{}", account_id, role_name));
+        sso.input.role = Some(ProfileInfo {
+            account_id: account_id.to_string(),
+            role_name: role_name.to_string(),
+            region: region.to_string(),
+            start_url: None,
+        });
+        sso
+    }
+
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    pub fn with_assume_role(mut self, assume_role: Option<AssumeRoleOptions>) -> Self {
+        self.assume_role = assume_role;
+        self
+    }
+
     pub fn login(&self) -> CustomResult<()> {
+        match self.get_profile_kind()? {
+            ProfileKind::Sso => {}
+            ProfileKind::Chained { source_profile, .. } => {
+                return self.source(source_profile)?.login();
+            }
+            ProfileKind::Static { .. } => {
+                self.logger
+                    .debug("Profile uses static credentials, no SSO login needed");
+                return Ok(());
+            }
+        }
+
         self.logger.debug("Logging in to AWS with SSO tokens");
 
         let sso_is_valid = self.sso_token_still_valid(constants::SSO_START_URL)?;
@@ -78,8 +178,11 @@ impl AwsSso {
         } else {
             self.logger
                 .debug("SSO token is not valid, checking for existing session...");
-            let command = "aws sso login --sso-session sso";
-            self.zsh_command.execute(command)?;
+            let command = CommandLine::new("aws")
+                .args(["sso", "login", "--sso-session", constants::SSO_SESSION])
+                // Waits for the login in the browser, whose device code expires in 10 minutes
+                .timeout(Duration::from_secs(10 * 60));
+            self.runner.execute(&command)?;
         }
 
         self.logger.debug("Logged in to AWS with SSO");
@@ -89,9 +192,95 @@ impl AwsSso {
 
     pub fn set_sso_credentials(&self) -> CustomResult<()> {
         self.logger.debug("Setting AWS SSO credentials");
-        let profile_info = self.get_sso_profile_info(&self.input.profile)?;
-        let token = self.get_latest_sso_token()?;
+        let creds = self.get_sso_credentials()?;
+
+        // Set them as env vars for current process
+        self.set_environment_variables(&creds)?;
+
+        // Long operations re-authenticate through this once the credentials expire
+        let refreshed = AwsSso {
+            input: self.input.clone(),
+            assume_role: self.assume_role.clone(),
+            refresh: true,
+            logger: Logger::new(),
+            runner: Arc::clone(&self.runner),
+            retry: RetryPolicy::new(),
+            chain: self.chain.clone(),
+        };
+        AwsSession::track(creds.expires_at()?, move || {
+            refreshed.login()?;
+            let creds = refreshed.get_sso_credentials()?;
+            refreshed.set_environment_variables(&creds)?;
+            creds.expires_at()
+        });
+
+        self.logger.debug("AWS SSO credentials set successfully");
+
+        Ok(())
+    }
+
+    /// Resolves the credentials for the profile. Profiles with `role_arn` and
+    /// `source_profile`, as well as an explicit `--assume-role`, are resolved
+    /// by chaining `sts assume-role` on top of the SSO role credentials.
+    /// Profiles without SSO settings use the IAM user keys of the profile.
+    pub fn get_sso_credentials(&self) -> CustomResult<RoleCredentials> {
+        let creds = match self.get_profile_kind()? {
+            ProfileKind::Sso => self.get_role_credentials()?,
+            ProfileKind::Chained {
+                source_profile,
+                options,
+            } => {
+                self.logger.debug(format!(
+                    "Profile '{}' assumes '{}' from profile '{}'",
+                    self.input.profile, options.role_arn, source_profile
+                ));
+                let source = self.source(source_profile)?;
+                // Exported without tracking, the chained credentials are tracked instead
+                let source_creds = source.get_sso_credentials()?;
+                source.set_environment_variables(&source_creds)?;
+                AwsSts::with_runner(Arc::clone(&self.runner)).assume_role(&options)?
+            }
+            ProfileKind::Static {
+                mfa_serial,
+                duration_seconds,
+            } => self.get_session_credentials(mfa_serial.as_deref(), duration_seconds)?,
+        };
+
+        match &self.assume_role {
+            Some(options) => {
+                self.set_environment_variables(&creds)?;
+                AwsSts::with_runner(Arc::clone(&self.runner)).assume_role(options)
+            }
+            None => Ok(creds),
+        }
+    }
 
+    /// Resolves the SSO role credentials for the profile without touching the
+    /// environment of the current process. Credentials are served from the
+    /// local role cache while they remain valid.
+    fn get_role_credentials(&self) -> CustomResult<RoleCredentials> {
+        let profile_info = match &self.input.role {
+            Some(role) => role.clone(),
+            None => self.get_sso_profile_info(&self.input.profile)?,
+        };
+
+        let cache = RoleCredentialCache::new()?;
+        let cache_key = RoleCredentialCache::key(
+            profile_info
+                .start_url
+                .as_deref()
+                .unwrap_or(constants::SSO_START_URL),
+            &profile_info.account_id,
+            &profile_info.role_name,
+        );
+
+        if !self.refresh {
+            if let Some(creds) = cache.get(&cache_key) {
+                return Ok(creds);
+            }
+        }
+
+        let token = self.get_latest_sso_token()?;
         let creds = self.execute_sso_command(
             &profile_info.account_id,
             &profile_info.role_name,
@@ -99,15 +288,15 @@ impl AwsSso {
             &profile_info.region,
         )?;
 
-        // Set them as env vars for current process
-        self.set_environment_variables(&creds)?;
-
-        self.logger.debug("AWS SSO credentials set successfully");
+        if let Err(err) = cache.put(&cache_key, &creds) {
+            self.logger
+                .warn(format!("Failed to cache role credentials: {}", err));
+        }
 
-        Ok(())
+        Ok(creds)
     }
 
-    fn get_latest_sso_token(&self) -> CustomResult<String> {
+    pub fn get_latest_sso_token(&self) -> CustomResult<String> {
         let cache_path = dirs::home_dir()
             .context("Failed to get home directory")
             .map_err(|err| {
@@ -117,10 +306,7 @@ impl AwsSso {
 
         let mut files: Vec<_> = fs::read_dir(&cache_path)
             .map_err(|err| {
-                CustomError::CommandExecution(format!(
-                    "Failed to read SSO cache directory: {}",
-                    err
-                ))
+                CustomError::Auth(format!("Failed to read SSO cache directory: {}", err))
             })?
             .filter_map(|e| e.ok())
             .filter(|e| {
@@ -132,9 +318,7 @@ impl AwsSso {
             .collect();
 
         if files.is_empty() {
-            return Err(CustomError::CommandExecution(
-                "No SSO cache files found".to_string(),
-            ));
+            return Err(CustomError::Auth("No SSO cache files found".to_string()));
         }
 
         files.sort_by_key(|e| e.metadata().and_then(|m| m.modified()).ok());
@@ -149,13 +333,15 @@ impl AwsSso {
             CustomError::CommandExecution(format!("Failed to read SSO cache file: {}", err))
         })?;
         let cache: CacheFile = serde_json::from_str(&contents).map_err(|err| {
-            CustomError::CommandExecution(format!("Failed to parse SSO cache file: {}", err))
+            CustomError::Parse(format!("Failed to parse SSO cache file: {}", err))
         })?;
 
         Ok(cache.access_token)
     }
 
-    fn get_profile_block(&self, config_contents: &str, profile_name: &str) -> CustomResult<String> {
+    /// The lines below the header of the profile, `[default]` being the
+    /// header of the default profile.
+    fn get_profile_block(&self, config_contents: &str, profile_name: &str) -> Option<String> {
         self.logger
             .debug(format!("Fetching profile block for '{}'", profile_name));
         let profile_header = format!("[profile {}]", profile_name);
@@ -164,13 +350,14 @@ impl AwsSso {
         let mut block = String::new();
 
         for line in lines {
-            if line.trim() == profile_header {
+            let header = line.trim();
+            if header == profile_header || (profile_name == "default" && header == "[default]") {
                 capture = true;
                 continue;
             }
 
             if capture {
-                if line.trim_start().starts_with("[profile") {
+                if header.starts_with('[') {
                     break;
                 }
                 block.push_str(line);
@@ -178,21 +365,14 @@ impl AwsSso {
             }
         }
 
-        if !capture {
-            self.logger.error(format!(
-                "Profile '{}' not found in AWS config",
-                profile_name
-            ));
-            Err(CustomError::CommandExecution(format!(
-                "Profile '{}' not found in AWS config",
-                profile_name
-            )))
-        } else {
+        if capture {
             self.logger.debug(format!(
                 "Profile block for '{}' fetched successfully",
                 profile_name
             ));
-            Ok(block)
+            Some(block)
+        } else {
+            None
         }
     }
 
@@ -202,6 +382,7 @@ impl AwsSso {
         let mut account_id = None;
         let mut role_name = None;
         let mut region = None;
+        let mut start_url = None;
 
         for line in profile_block.lines() {
             if let Some((key, value)) = line.split_once('=') {
@@ -212,6 +393,7 @@ impl AwsSso {
                     "sso_account_id" => account_id = Some(value.to_string()),
                     "sso_role_name" => role_name = Some(value.to_string()),
                     "region" => region = Some(value.to_string()),
+                    "sso_start_url" => start_url = Some(value.to_string()),
                     _ => {}
                 }
             }
@@ -222,11 +404,12 @@ impl AwsSso {
                 account_id,
                 role_name,
                 region,
+                start_url,
             }),
             _ => {
                 self.logger
                     .error("Missing one or more required fields in profile block");
-                Err(CustomError::CommandExecution(
+                Err(CustomError::Config(
                     "Missing one or more required fields in profile block".to_string(),
                 ))
             }
@@ -236,6 +419,160 @@ impl AwsSso {
     fn get_sso_profile_info(&self, profile_name: &str) -> CustomResult<ProfileInfo> {
         self.logger
             .debug(format!("Fetching SSO profile info for '{}'", profile_name));
+        let profile_block = self.read_profile_block(profile_name)?.ok_or_else(|| {
+            CustomError::Config(format!(
+                "Profile '{}' not found in AWS config",
+                profile_name
+            ))
+        })?;
+        let values = self.parse_profile_values(&profile_block)?;
+
+        Ok(values)
+    }
+
+    /// The profile named by `source_profile`, refusing chains that loop back.
+    fn source(&self, source_profile: String) -> CustomResult<Self> {
+        let mut chain = self.chain.clone();
+        chain.push(self.input.profile.clone());
+
+        if chain.contains(&source_profile) {
+            return Err(CustomError::Config(format!(
+                "Profiles form a source_profile cycle: {} -> {}",
+                chain.join(" -> "),
+                source_profile
+            )));
+        }
+
+        let mut source = AwsSso::new(source_profile).with_runner(Arc::clone(&self.runner));
+        source.refresh = self.refresh;
+        source.chain = chain;
+        Ok(source)
+    }
+
+    /// Determines how the credentials of the profile are obtained from the
+    /// keys of its block in ~/.aws/config.
+    fn get_profile_kind(&self) -> CustomResult<ProfileKind> {
+        if self.input.role.is_some() {
+            return Ok(ProfileKind::Sso);
+        }
+
+        let Some(profile_block) = self.read_profile_block(&self.input.profile)? else {
+            // IAM user keys often live in ~/.aws/credentials alone
+            if CredentialsFile::new()?.has_profile(&self.input.profile)? {
+                return Ok(ProfileKind::Static {
+                    mfa_serial: None,
+                    duration_seconds: None,
+                });
+            }
+
+            return Err(CustomError::Config(format!(
+                "Profile '{}' not found in ~/.aws/config or ~/.aws/credentials",
+                self.input.profile
+            )));
+        };
+        let mut has_sso_account = false;
+        let mut role_arn = None;
+        let mut source_profile = None;
+        let mut session_name = None;
+        let mut duration_seconds = None;
+        let mut external_id = None;
+        let mut mfa_serial = None;
+
+        for line in profile_block.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().to_string();
+
+                match key.trim() {
+                    "sso_account_id" => has_sso_account = true,
+                    "role_arn" => role_arn = Some(value),
+                    "source_profile" => source_profile = Some(value),
+                    "role_session_name" => session_name = Some(value),
+                    "duration_seconds" => duration_seconds = value.parse().ok(),
+                    "external_id" => external_id = Some(value),
+                    "mfa_serial" => mfa_serial = Some(value),
+                    _ => {}
+                }
+            }
+        }
+
+        if let (Some(role_arn), Some(source_profile)) = (role_arn, source_profile) {
+            let mut options = AssumeRoleOptions::new(role_arn);
+            options.session_name = session_name.unwrap_or(options.session_name);
+            options.duration_seconds = duration_seconds;
+            options.external_id = external_id;
+
+            return Ok(ProfileKind::Chained {
+                source_profile,
+                options,
+            });
+        }
+
+        if has_sso_account {
+            Ok(ProfileKind::Sso)
+        } else {
+            Ok(ProfileKind::Static {
+                mfa_serial,
+                duration_seconds,
+            })
+        }
+    }
+
+    /// Exchanges the IAM user keys of a static profile for a session token,
+    /// prompting for the TOTP code when the profile has an `mfa_serial`. The
+    /// session is cached until shortly before it expires.
+    fn get_session_credentials(
+        &self,
+        mfa_serial: Option<&str>,
+        duration_seconds: Option<u32>,
+    ) -> CustomResult<RoleCredentials> {
+        let cache = RoleCredentialCache::new()?;
+        let cache_key = RoleCredentialCache::key(
+            "static",
+            &self.input.profile,
+            mfa_serial.unwrap_or("no-mfa"),
+        );
+
+        if !self.refresh {
+            if let Some(creds) = cache.get(&cache_key) {
+                return Ok(creds);
+            }
+        }
+
+        let mfa = match mfa_serial {
+            Some(serial) => Some((serial, self.prompt_mfa_code(serial)?)),
+            None => None,
+        };
+
+        let creds = AwsSts::with_runner(Arc::clone(&self.runner)).get_session_token(
+            &self.input.profile,
+            mfa.as_ref().map(|(serial, code)| (*serial, code.as_str())),
+            duration_seconds,
+        )?;
+
+        if let Err(err) = cache.put(&cache_key, &creds) {
+            self.logger
+                .warn(format!("Failed to cache session credentials: {}", err));
+        }
+
+        Ok(creds)
+    }
+
+    fn prompt_mfa_code(&self, mfa_serial: &str) -> CustomResult<String> {
+        Input::<String>::new()
+            .with_prompt(format!("MFA code for {}", mfa_serial))
+            .validate_with(|input: &String| -> Result<(), &str> {
+                if input.len() == 6 && input.chars().all(|c| c.is_ascii_digit()) {
+                    Ok(())
+                } else {
+                    Err("The MFA code must be 6 digits")
+                }
+            })
+            .interact_text()
+            .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))
+    }
+
+    /// The block of the profile in ~/.aws/config, if the file has one.
+    fn read_profile_block(&self, profile_name: &str) -> CustomResult<Option<String>> {
         let config_path = dirs::home_dir()
             .context("Failed to get home directory")
             .map_err(|err| {
@@ -245,16 +582,20 @@ impl AwsSso {
             })?
             .join(".aws/config");
 
-        let contents = fs::read_to_string(config_path).map_err(|err| {
-            self.logger
-                .error(format!("Failed to read AWS config file: {}", err));
-            CustomError::CommandExecution(format!("Failed to read AWS config file: {}", err))
-        })?;
-
-        let profile_block = self.get_profile_block(&contents, profile_name)?;
-        let values = self.parse_profile_values(&profile_block)?;
+        let contents = match fs::read_to_string(config_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                self.logger
+                    .error(format!("Failed to read AWS config file: {}", err));
+                return Err(CustomError::Config(format!(
+                    "Failed to read AWS config file: {}",
+                    err
+                )));
+            }
+        };
 
-        Ok(values)
+        Ok(self.get_profile_block(&contents, profile_name))
     }
 
     fn execute_sso_command(
@@ -264,17 +605,29 @@ impl AwsSso {
         token: &str,
         region: &str,
     ) -> CustomResult<RoleCredentials> {
-        let command = format!(
-            "aws sso get-role-credentials --account-id {} --role-name {} --access-token {} --region {}",
-            account_id, role_name, token, region
-        );
+        // The access token goes through stdin to stay out of the process list
+        let input = serde_json::json!({
+            "accountId": account_id,
+            "roleName": role_name,
+            "accessToken": token,
+        });
+        let command = CommandLine::new("aws")
+            .args([
+                "sso",
+                "get-role-credentials",
+                "--cli-input-json",
+                "file:///dev/stdin",
+                "--region",
+                region,
+            ])
+            .stdin(input.to_string());
 
-        let output = self.zsh_command.execute(&command)?;
+        let output = self.retry.run(|| self.runner.execute(&command))?;
 
         let resp: SsoResponse = serde_json::from_slice(&output.stdout).map_err(|err| {
             self.logger
                 .error(format!("Failed to parse AWS CLI response: {}", err));
-            CustomError::CommandExecution(format!("Failed to parse AWS CLI response: {}", err))
+            CustomError::Parse(format!("Failed to parse AWS CLI response: {}", err))
         })?;
         Ok(resp.role_credentials)
     }
@@ -292,6 +645,16 @@ impl AwsSso {
     }
 
     fn sso_token_still_valid(&self, sso_start_url: &str) -> CustomResult<bool> {
+        let now = Utc::now();
+
+        Ok(self
+            .sso_tokens()?
+            .iter()
+            .any(|token| token.start_url == sso_start_url && token.expires_at > now))
+    }
+
+    /// Lists the SSO access tokens cached by the AWS CLI in ~/.aws/sso/cache.
+    pub fn sso_tokens(&self) -> CustomResult<Vec<SsoToken>> {
         let cache_dir = home_dir()
             .ok_or("Failed to get home directory")
             .map_err(|err| CustomError::CommandExecution(err.to_string()))?
@@ -306,6 +669,8 @@ impl AwsSso {
         let paths =
             glob(glob_pattern).map_err(|err| CustomError::CommandExecution(err.to_string()))?;
 
+        let mut tokens = Vec::new();
+
         for entry in paths {
             let path = match entry {
                 Ok(p) => p,
@@ -337,12 +702,161 @@ impl AwsSso {
             if let (Some(start_url), Some(expires_at)) =
                 (cache_entry.start_url, cache_entry.expires_at)
             {
-                if start_url == sso_start_url && expires_at > Utc::now() {
-                    return Ok(true);
-                }
+                tokens.push(SsoToken {
+                    path,
+                    start_url,
+                    expires_at,
+                });
             }
         }
 
-        Ok(false)
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use chrono::Duration as ChronoDuration;
+
+    use super::*;
+    use crate::command::FakeRunner;
+
+    /// Points HOME and the cache dir of this process at a temp dir holding an
+    /// AWS config with one profile per test, an SSO token and the keys of an
+    /// IAM user profile missing from the config. Set up once,
+    /// since the environment is shared by the tests running in parallel.
+    fn home() {
+        static HOME: OnceLock<PathBuf> = OnceLock::new();
+
+        HOME.get_or_init(|| {
+            let home = env::temp_dir().join(format!("raw-sso-test-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&home);
+            fs::create_dir_all(home.join(".aws/sso/cache")).unwrap();
+
+            let mut config = String::new();
+            for (profile, role) in [("caches", "ReadOnly"), ("denied", "Admin")] {
+                config.push_str(&format!(
+                    "[profile {}]\nsso_start_url = {}\nsso_account_id = 111111111111\nsso_role_name = {}\nregion = eu-west-1\n",
+                    profile,
+                    constants::SSO_START_URL,
+                    role
+                ));
+            }
+            fs::write(home.join(".aws/config"), config).unwrap();
+            fs::write(
+                home.join(".aws/credentials"),
+                "[iam]\naws_access_key_id = AKIAIAM\naws_secret_access_key = secret\n",
+            )
+            .unwrap();
+            fs::write(
+                home.join(".aws/sso/cache/token.json"),
+                r#"{"accessToken": "token", "startUrl": "https://example.com"}"#,
+            )
+            .unwrap();
+
+            env::set_var("HOME", &home);
+            env::set_var("XDG_CACHE_HOME", home.join(".cache"));
+            home
+        });
+    }
+
+    /// The role and the cached SSO token of the test config, read from
+    /// stdin.
+    fn get_role_credentials(role: &str) -> CommandLine {
+        CommandLine::new("aws")
+            .args([
+                "sso",
+                "get-role-credentials",
+                "--cli-input-json",
+                "file:///dev/stdin",
+                "--region",
+                "eu-west-1",
+            ])
+            .stdin(
+                serde_json::json!({
+                    "accountId": "111111111111",
+                    "roleName": role,
+                    "accessToken": "token",
+                })
+                .to_string(),
+            )
+    }
+
+    #[test]
+    fn caches_role_credentials_of_profile() {
+        home();
+        let expiration = (Utc::now() + ChronoDuration::hours(1)).timestamp_millis();
+        let runner = Arc::new(FakeRunner::new().expect(
+            get_role_credentials("ReadOnly"),
+            &format!(
+                r#"{{"roleCredentials": {{"accessKeyId": "AKIA", "secretAccessKey": "secret", "sessionToken": "session", "expiration": {}}}}}"#,
+                expiration
+            ),
+        ));
+        let sso = AwsSso::new("caches".to_string()).with_runner(runner.clone());
+
+        let creds = sso.get_sso_credentials().unwrap();
+        // Served from the role cache, the runner answers only once
+        let cached = sso.get_sso_credentials().unwrap();
+
+        assert_eq!(creds.access_key_id, "AKIA");
+        assert_eq!(creds.expiration, expiration);
+        assert_eq!(cached.session_token, "session");
+        runner.verify().unwrap();
+    }
+
+    #[test]
+    fn reports_denied_role() {
+        home();
+        let runner = Arc::new(FakeRunner::new().expect_failure(
+            get_role_credentials("Admin"),
+            "An error occurred (ForbiddenException) when calling the GetRoleCredentials operation: No access",
+        ));
+        let sso = AwsSso::new("denied".to_string()).with_runner(runner.clone());
+
+        let result = sso.get_sso_credentials();
+
+        assert!(matches!(result, Err(CustomError::AccessDenied(_))));
+        runner.verify().unwrap();
+    }
+
+    #[test]
+    fn uses_iam_user_profile_of_credentials_file() {
+        home();
+        let expiration = Utc::now() + ChronoDuration::hours(1);
+        let runner = Arc::new(FakeRunner::new().expect(
+            CommandLine::new("aws").args([
+                "sts",
+                "get-session-token",
+                "--profile",
+                "iam",
+                "--output",
+                "json",
+            ]),
+            &format!(
+                r#"{{"Credentials": {{"AccessKeyId": "ASIA", "SecretAccessKey": "secret", "SessionToken": "session", "Expiration": "{}"}}}}"#,
+                expiration.to_rfc3339()
+            ),
+        ));
+        let sso = AwsSso::new("iam".to_string()).with_runner(runner.clone());
+
+        // No SSO login for a profile with IAM user keys
+        sso.login().unwrap();
+        let creds = sso.get_sso_credentials().unwrap();
+
+        assert_eq!(creds.access_key_id, "ASIA");
+        runner.verify().unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_profile() {
+        home();
+        let runner = Arc::new(FakeRunner::new());
+        let sso = AwsSso::new("unknown".to_string()).with_runner(runner.clone());
+
+        assert!(matches!(sso.login(), Err(CustomError::Config(_))));
+        runner.verify().unwrap();
     }
 }
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+    aws::{
+        aws_codeartifact::CodeArtifactState, aws_role_cache::RoleCredentialCache, aws_sso::AwsSso,
+        aws_sts::AwsSts,
+    },
+    config::AppConfig,
+    constants,
+    custom_error::CustomResult,
+    logger::Logger,
+};
+
+pub struct AwsStatus {
+    logger: Logger,
+}
+
+impl AwsStatus {
+    pub fn new() -> Self {
+        Self {
+            logger: Logger::new(),
+        }
+    }
+
+    /// Prints the session state of the tool. When `profile` is given, the
+    /// caller identity is resolved with the role credentials of that profile
+    /// instead of whatever credentials the environment provides.
+    pub fn report(&self, profile: Option<&str>) -> CustomResult<()> {
+        match AppConfig::find_config_file() {
+            Some(path) => println!("Config file: {}", path.display()),
+            None => println!("Config file: none, using defaults"),
+        }
+
+        println!("\nSSO tokens:");
+        let sso = AwsSso::new(profile.unwrap_or(constants::DEV_ROLE).to_string());
+        let tokens = sso.sso_tokens()?;
+        if tokens.is_empty() {
+            println!("  none");
+        }
+        for token in tokens {
+            println!(
+                "  {}: {}",
+                token.start_url,
+                Self::describe_expiry(token.expires_at)
+            );
+        }
+
+        println!("\nCached role credentials:");
+        let cached = RoleCredentialCache::new()?.entries();
+        if cached.is_empty() {
+            println!("  none");
+        }
+        for (key, expires_at) in cached {
+            println!("  {}: {}", key, Self::describe_expiry(expires_at));
+        }
+
+        println!("\nCodeArtifact:");
+        let logins = CodeArtifactState::new()?.entries();
+        if logins.is_empty() {
+            println!("  no recorded logins");
+        }
+        for (tool, expires_at) in logins {
+            println!("  {}: {}", tool, Self::describe_expiry(expires_at));
+        }
+
+        println!("\nCaller identity:");
+        if let Some(profile) = profile {
+            sso.set_sso_credentials()?;
+            println!("  profile: {}", profile);
+        }
+        match AwsSts::new().get_caller_identity() {
+            Ok(identity) => {
+                println!("  account: {}", identity.account);
+                println!("  arn: {}", identity.arn);
+            }
+            Err(err) => {
+                self.logger
+                    .debug(format!("Failed to get caller identity: {}", err));
+                println!("  unavailable, no valid credentials in the environment");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn describe_expiry(expires_at: DateTime<Utc>) -> String {
+        let remaining = expires_at - Utc::now();
+
+        if remaining.num_seconds() <= 0 {
+            format!("expired at {}", expires_at.to_rfc3339())
+        } else {
+            format!(
+                "valid for {}h {}m (until {})",
+                remaining.num_hours(),
+                remaining.num_minutes() % 60,
+                expires_at.to_rfc3339()
+            )
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    aws::aws_sso::RoleCredentials,
+    command::{self, CommandLine, CommandRunner},
+    custom_error::{CustomError, CustomResult},
+    logger::Logger,
+};
+
+/// Parameters of an `sts assume-role` call chained on top of other credentials.
+#[derive(Debug, Clone)]
+pub struct AssumeRoleOptions {
+    pub role_arn: String,
+    pub session_name: String,
+    pub duration_seconds: Option<u32>,
+    pub external_id: Option<String>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl AssumeRoleOptions {
+    pub fn new(role_arn: String) -> Self {
+        Self {
+            role_arn,
+            session_name: Self::default_session_name(),
+            duration_seconds: None,
+            external_id: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn default_session_name() -> String {
+        format!(
+            "raw-{}",
+            std::env::var("USER").unwrap_or_else(|_| "session".to_string())
+        )
+    }
+}
+
+/// Response shape from `aws sts assume-role` and `aws sts get-session-token`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CredentialsResponse {
+    credentials: StsCredentials,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    expiration: DateTime<Utc>,
+}
+
+impl From<StsCredentials> for RoleCredentials {
+    fn from(creds: StsCredentials) -> Self {
+        Self {
+            access_key_id: creds.access_key_id,
+            secret_access_key: creds.secret_access_key,
+            session_token: creds.session_token,
+            expiration: creds.expiration.timestamp_millis(),
+        }
+    }
+}
+
+/// Response shape from `aws sts get-caller-identity`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CallerIdentity {
+    pub account: String,
+    pub arn: String,
+}
+
+pub struct AwsSts {
+    logger: Logger,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl AwsSts {
+    pub fn new() -> Self {
+        Self::with_runner(command::default_runner())
+    }
+
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
+            logger: Logger::new(),
+            runner,
+        }
+    }
+
+    /// Assumes `options.role_arn` with the credentials currently set in the
+    /// environment of this process, e.g. by `AwsSso::set_sso_credentials`.
+    pub fn assume_role(&self, options: &AssumeRoleOptions) -> CustomResult<RoleCredentials> {
+        self.logger.debug(format!(
+            "Assuming role '{}' with session '{}'",
+            options.role_arn, options.session_name
+        ));
+
+        let mut command = CommandLine::new("aws").args([
+            "sts",
+            "assume-role",
+            "--role-arn",
+            &options.role_arn,
+            "--role-session-name",
+            &options.session_name,
+            "--output",
+            "json",
+        ]);
+
+        if let Some(duration) = options.duration_seconds {
+            command = command.arg("--duration-seconds").arg(duration.to_string());
+        }
+
+        // Free-form values go through stdin, as shorthand arguments a `,` or
+        // `=` in a tag value would be split by the AWS CLI
+        let mut input = serde_json::Map::new();
+        if let Some(ref external_id) = options.external_id {
+            input.insert("ExternalId".to_string(), json!(external_id));
+        }
+        if !options.tags.is_empty() {
+            let tags: Vec<_> = options
+                .tags
+                .iter()
+                .map(|(key, value)| json!({ "Key": key, "Value": value }))
+                .collect();
+            input.insert("Tags".to_string(), json!(tags));
+        }
+        if !input.is_empty() {
+            command = command
+                .args(["--cli-input-json", "file:///dev/stdin"])
+                .stdin(serde_json::Value::Object(input).to_string());
+        }
+
+        let output = self.runner.execute(&command)?;
+
+        let response: CredentialsResponse =
+            serde_json::from_slice(&output.stdout).map_err(|err| {
+                CustomError::Parse(format!("Failed to parse assume-role response: {}", err))
+            })?;
+
+        self.logger
+            .debug(format!("Assumed role '{}'", options.role_arn));
+
+        Ok(response.credentials.into())
+    }
+
+    /// Exchanges the IAM user keys of `profile` for temporary credentials,
+    /// authenticated with `mfa` as `(serial number, token code)` when given.
+    pub fn get_session_token(
+        &self,
+        profile: &str,
+        mfa: Option<(&str, &str)>,
+        duration_seconds: Option<u32>,
+    ) -> CustomResult<RoleCredentials> {
+        self.logger
+            .debug(format!("Getting session token for profile '{}'", profile));
+
+        let mut command = CommandLine::new("aws").args([
+            "sts",
+            "get-session-token",
+            "--profile",
+            profile,
+            "--output",
+            "json",
+        ]);
+
+        // The token code goes through stdin to stay out of the process list
+        if let Some((serial_number, token_code)) = mfa {
+            let input = json!({
+                "SerialNumber": serial_number,
+                "TokenCode": token_code,
+            });
+            command = command
+                .args(["--cli-input-json", "file:///dev/stdin"])
+                .stdin(input.to_string());
+        }
+
+        if let Some(duration) = duration_seconds {
+            command = command.arg("--duration-seconds").arg(duration.to_string());
+        }
+
+        let output = self.runner.execute(&command)?;
+
+        let response: CredentialsResponse =
+            serde_json::from_slice(&output.stdout).map_err(|err| {
+                CustomError::Parse(format!(
+                    "Failed to parse get-session-token response: {}",
+                    err
+                ))
+            })?;
+
+        self.logger
+            .debug(format!("Got session token for profile '{}'", profile));
+
+        Ok(response.credentials.into())
+    }
+
+    /// Identity of the credentials currently set in the environment.
+    pub fn get_caller_identity(&self) -> CustomResult<CallerIdentity> {
+        let output = self.runner.execute(&CommandLine::new("aws").args([
+            "sts",
+            "get-caller-identity",
+            "--output",
+            "json",
+        ]))?;
+
+        serde_json::from_slice(&output.stdout).map_err(|err| {
+            CustomError::Parse(format!(
+                "Failed to parse get-caller-identity response: {}",
+                err
+            ))
+        })
+    }
+}
@@ -1,4 +1,13 @@
 pub mod aws_cli;
+pub mod aws_codeartifact;
+pub mod aws_credentials_file;
+pub mod aws_discovery;
+pub mod aws_env;
+pub mod aws_logout;
 pub mod aws_logs;
 pub mod aws_pr;
+pub mod aws_role_cache;
+pub mod aws_session;
 pub mod aws_sso;
+pub mod aws_status;
+pub mod aws_sts;
@@ -1,10 +1,75 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::aws::{
+    aws_env::EnvFormat,
+    aws_pr::{MergeStrategy, PrStatus},
+    aws_sts::AssumeRoleOptions,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Print the AWS and git commands and the file writes instead of running them
+    #[arg(long, global = true, conflicts_with_all = ["record", "replay"])]
+    pub dry_run: bool,
+
+    /// Record every command and its output to this fixture file
+    #[arg(long, global = true, hide = true, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer commands from this fixture file instead of running them
+    #[arg(long, global = true, hide = true)]
+    pub replay: Option<PathBuf>,
+}
+
+/// Options for assuming a role on top of the profile credentials.
+#[derive(Args)]
+pub struct AssumeRoleArgs {
+    /// ARN of a role to assume with the profile credentials
+    #[arg(long)]
+    pub assume_role: Option<String>,
+
+    /// Session name of the assumed role
+    #[arg(long, requires = "assume_role")]
+    pub role_session_name: Option<String>,
+
+    /// Duration of the assumed role session in seconds
+    #[arg(long, requires = "assume_role")]
+    pub duration_seconds: Option<u32>,
+
+    /// External ID required by the trust policy of the assumed role
+    #[arg(long, requires = "assume_role")]
+    pub external_id: Option<String>,
+
+    /// Session tag for the assumed role, as KEY=VALUE (repeatable)
+    #[arg(long = "tag", requires = "assume_role", value_parser = parse_key_value)]
+    pub tags: Vec<(String, String)>,
+}
+
+impl AssumeRoleArgs {
+    pub fn into_options(self) -> Option<AssumeRoleOptions> {
+        let mut options = AssumeRoleOptions::new(self.assume_role?);
+        if let Some(session_name) = self.role_session_name {
+            options.session_name = session_name;
+        }
+        options.duration_seconds = self.duration_seconds;
+        options.external_id = self.external_id;
+        options.tags = self.tags;
+
+        Some(options)
+    }
+}
+
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", value))
 }
 
 #[derive(Subcommand)]
@@ -23,16 +88,232 @@ pub enum Commands {
         #[arg(short, long)]
         target: String,
 
+        /// Description, generated from the commits of the source branch when omitted
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// Open $EDITOR on the description before creating the pull request
+        #[arg(short, long, default_value_t = false)]
+        edit: bool,
+
+        /// Merge strategy to check for conflicts with
+        #[arg(long, value_enum, default_value_t = MergeStrategy::ThreeWay)]
+        merge_strategy: MergeStrategy,
+
         /// Abort on merge conflicts
         #[arg(short, long, default_value_t = false)]
         abort: bool,
+
+        /// Attach this approval rule from [pr.approval_rules] of the config file
+        #[arg(long, conflicts_with = "approval_rule_json")]
+        approval_rule: Option<String>,
+
+        /// Attach an approval rule given as CodeCommit rule content JSON
+        #[arg(long)]
+        approval_rule_json: Option<String>,
+    },
+    /// Manage pull requests of the current repository
+    Pr {
+        #[command(subcommand)]
+        command: PrCommands,
+    },
+    Login {
+        /// Also write the role credentials into this profile of ~/.aws/credentials
+        #[arg(short, long)]
+        write_credentials: Option<String>,
+
+        /// Overwrite the credentials of a profile this tool didn't write
+        #[arg(long, requires = "write_credentials")]
+        force: bool,
+
+        #[command(flatten)]
+        assume_role: AssumeRoleArgs,
     },
-    Login {},
     LoginNpm {},
     LoginPip {},
     Morning {},
     /// Fetch CloudWatch log events and save to logs.json
-    Logs {},
+    Logs {
+        /// Pick the account and role from SSO instead of ~/.aws/config profiles
+        #[arg(short, long, default_value_t = false)]
+        discover: bool,
+    },
     /// Fetch CloudWatch log events (provide AWS credentials directly, no SSO)
     LogsRaw {},
+    /// Print role credentials of a profile for the parent shell
+    Env {
+        /// AWS profile to resolve credentials for
+        profile: String,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = EnvFormat::Bash)]
+        format: EnvFormat,
+
+        #[command(flatten)]
+        assume_role: AssumeRoleArgs,
+    },
+    /// Run a command with role credentials of a profile injected
+    Exec {
+        /// AWS profile to resolve credentials for
+        profile: String,
+
+        /// Command to run, given after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+
+        #[command(flatten)]
+        assume_role: AssumeRoleArgs,
+    },
+    /// Discover SSO accounts and roles and optionally generate their profiles
+    Discover {
+        /// Append the profile of the selected role to ~/.aws/config
+        #[arg(short, long, default_value_t = false)]
+        write_config: bool,
+
+        /// Append profiles for every account and role to ~/.aws/config
+        #[arg(short, long, default_value_t = false)]
+        all: bool,
+    },
+    /// Show SSO sessions, cached credentials and the current identity
+    Status {
+        /// Resolve the caller identity with the credentials of this profile
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Revoke the SSO session and remove cached or written credentials
+    Logout {
+        /// Also remove the CodeArtifact registry from npm config
+        #[arg(long, default_value_t = false)]
+        npm: bool,
+
+        /// Also remove the CodeArtifact index from pip config
+        #[arg(long, default_value_t = false)]
+        pip: bool,
+    },
+    /// Print role credentials in the AWS `credential_process` JSON format
+    CredentialProcess {
+        /// AWS profile to resolve credentials for
+        #[arg(short, long)]
+        profile: String,
+
+        #[command(flatten)]
+        assume_role: AssumeRoleArgs,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PrCommands {
+    /// List pull requests
+    List {
+        /// Pull request status
+        #[arg(short, long, value_enum, default_value_t = PrStatus::Open)]
+        status: PrStatus,
+
+        /// Only pull requests authored by the current caller
+        #[arg(short, long, default_value_t = false)]
+        mine: bool,
+
+        /// Repository name, defaults to the current git repository
+        #[arg(short, long)]
+        repo: Option<String>,
+    },
+    /// Show the details of a pull request
+    Show {
+        /// Pull request ID
+        id: String,
+
+        /// Repository name, defaults to the current git repository
+        #[arg(short, long)]
+        repo: Option<String>,
+    },
+    /// Merge a pull request
+    Merge {
+        /// Pull request ID
+        id: String,
+
+        /// Merge strategy
+        #[arg(short, long, value_enum, default_value_t = MergeStrategy::FastForward)]
+        strategy: MergeStrategy,
+
+        /// Commit message of a squash or three-way merge
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Author name of a squash or three-way merge commit
+        #[arg(long)]
+        author_name: Option<String>,
+
+        /// Author email of a squash or three-way merge commit
+        #[arg(long)]
+        email: Option<String>,
+
+        /// Delete the source branch after merging
+        #[arg(short, long, default_value_t = false)]
+        delete_branch: bool,
+
+        /// Repository name, defaults to the current git repository
+        #[arg(short, long)]
+        repo: Option<String>,
+    },
+    /// Approve a pull request, by default the one of the current branch
+    Approve {
+        /// Pull request ID
+        id: Option<String>,
+
+        /// Repository name, defaults to the current git repository
+        #[arg(short, long)]
+        repo: Option<String>,
+    },
+    /// Revoke an approval, by default of the pull request of the current branch
+    Revoke {
+        /// Pull request ID
+        id: Option<String>,
+
+        /// Repository name, defaults to the current git repository
+        #[arg(short, long)]
+        repo: Option<String>,
+    },
+    /// Comment on a pull request, by default the one of the current branch
+    Comment {
+        /// Pull request ID
+        id: Option<String>,
+
+        /// Comment text
+        #[arg(short, long)]
+        message: String,
+
+        /// File the comment refers to
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// Line of the file the comment refers to
+        #[arg(short, long, requires = "file")]
+        line: Option<u64>,
+
+        /// Repository name, defaults to the current git repository
+        #[arg(short, long)]
+        repo: Option<String>,
+    },
+    /// Update the title or description of a pull request
+    Edit {
+        /// Pull request ID
+        id: String,
+
+        /// New title
+        #[arg(short, long)]
+        title: Option<String>,
+
+        /// New description
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// Open $EDITOR on the description
+        #[arg(short, long, default_value_t = false)]
+        edit: bool,
+    },
+    /// Close a pull request without merging it
+    Close {
+        /// Pull request ID
+        id: String,
+    },
 }
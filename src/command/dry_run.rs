@@ -0,0 +1,127 @@
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+use crate::custom_error::CustomResult;
+
+use super::{CommandLine, CommandOutput, CommandRunner, ProcessRunner};
+
+/// git subcommands that only read the repository, run for real in dry-run
+/// mode so the flow sees the actual branch, commits and working tree.
+const READ_ONLY_GIT: &[&str] = &["rev-parse", "log", "status", "for-each-ref", "rev-list"];
+
+/// Prints commands instead of running them and answers with responses just
+/// plausible enough for the calling flow to continue.
+pub struct DryRunRunner {
+    inner: ProcessRunner,
+}
+
+impl DryRunRunner {
+    pub fn new() -> Self {
+        Self {
+            inner: ProcessRunner::new(),
+        }
+    }
+
+    fn is_read_only(command: &CommandLine) -> bool {
+        if command.program != "git" {
+            return false;
+        }
+
+        match command.args.first().map(String::as_str) {
+            Some("branch") => command.args[1..] == ["--show-current"],
+            Some("remote") => command.args[1..] == ["-v"],
+            Some(subcommand) => READ_ONLY_GIT.contains(&subcommand),
+            None => false,
+        }
+    }
+
+    fn synthetic_stdout(command: &CommandLine) -> String {
+        if command.program != "aws" {
+            return String::new();
+        }
+
+        let args: Vec<&str> = command.args.iter().map(String::as_str).collect();
+        let expires_at = Utc::now() + Duration::hours(1);
+        let pull_request = json!({
+            "pullRequestId": Self::arg_value(&args, "--pull-request-id").unwrap_or("0"),
+            "title": "dry run",
+            "pullRequestStatus": "OPEN",
+            "authorArn": "arn:aws:sts::000000000000:assumed-role/dry-run/dry-run",
+            "revisionId": "0",
+            "pullRequestTargets": [{
+                "repositoryName": Self::arg_value(&args, "--repository-name").unwrap_or("dry-run"),
+                "sourceReference": "refs/heads/dry-run",
+                "destinationReference": "refs/heads/dry-run",
+                "sourceCommit": "0",
+                "destinationCommit": "0",
+            }],
+        });
+
+        let response = match args.as_slice() {
+            ["sso", "get-role-credentials", ..] => json!({
+                "roleCredentials": {
+                    "accessKeyId": "DRYRUN",
+                    "secretAccessKey": "DRYRUN",
+                    "sessionToken": "DRYRUN",
+                    "expiration": expires_at.timestamp_millis(),
+                }
+            }),
+            ["sts", "assume-role" | "get-session-token", ..] => json!({
+                "Credentials": {
+                    "AccessKeyId": "DRYRUN",
+                    "SecretAccessKey": "DRYRUN",
+                    "SessionToken": "DRYRUN",
+                    "Expiration": expires_at.to_rfc3339(),
+                }
+            }),
+            ["sts", "get-caller-identity", ..] => json!({
+                "Account": "000000000000",
+                "Arn": "arn:aws:sts::000000000000:assumed-role/dry-run/dry-run",
+            }),
+            ["sso", "list-accounts", ..] => json!({ "accountList": [] }),
+            ["sso", "list-account-roles", ..] => json!({ "roleList": [] }),
+            ["codeartifact", "login", ..] => {
+                return "Successfully configured the tool to use AWS CodeArtifact (dry run)"
+                    .to_string()
+            }
+            ["codecommit", "get-merge-conflicts", ..] => {
+                json!({ "mergeable": true, "conflictMetadataList": [] })
+            }
+            ["codecommit", "list-pull-requests", ..] => json!({ "pullRequestIds": [] }),
+            ["codecommit", "get-pull-request-approval-states", ..] => json!({ "approvals": [] }),
+            ["codecommit", "evaluate-pull-request-approval-rules", ..] => {
+                json!({ "evaluation": { "approved": true, "overridden": false } })
+            }
+            ["codecommit", operation, ..] if operation.contains("pull-request") => {
+                json!({ "pullRequest": pull_request })
+            }
+            ["logs", "describe-log-groups", ..] => json!({ "logGroups": [] }),
+            ["logs", "filter-log-events", ..] => json!({ "events": [] }),
+            _ => json!({}),
+        };
+
+        response.to_string()
+    }
+
+    fn arg_value<'a>(args: &[&'a str], name: &str) -> Option<&'a str> {
+        args.iter()
+            .position(|arg| *arg == name)
+            .and_then(|index| args.get(index + 1).copied())
+    }
+}
+
+impl CommandRunner for DryRunRunner {
+    fn run(&self, command: &CommandLine) -> CustomResult<CommandOutput> {
+        eprintln!("[dry-run] {}", command);
+
+        if Self::is_read_only(command) {
+            return self.inner.run(command);
+        }
+
+        Ok(CommandOutput {
+            exit_code: 0,
+            stdout: Self::synthetic_stdout(command).into_bytes(),
+            stderr: Vec::new(),
+        })
+    }
+}
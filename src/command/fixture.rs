@@ -0,0 +1,382 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+};
+
+use super::{CommandLine, CommandOutput, CommandRunner, ProcessRunner};
+
+/// JSON fields holding secrets, in stdin or in the output of a command.
+/// Recorded as `***`, which matches any value on replay.
+const SECRET_FIELDS: &[&str] = &[
+    "accessToken",
+    "TokenCode",
+    "secretAccessKey",
+    "sessionToken",
+    "SecretAccessKey",
+    "SessionToken",
+];
+
+const MASK: &str = "***";
+
+/// One command and its result as stored in a fixture file. Secrets in stdin
+/// and stdout are masked when recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Invocation {
+    program: String,
+    args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stdin: Option<String>,
+    exit_code: i32,
+    #[serde(default)]
+    stdout: String,
+    #[serde(default)]
+    stderr: String,
+}
+
+impl Invocation {
+    /// Stdin only has to match when the invocation has one; JSON input is
+    /// compared as values so key order and whitespace don't matter.
+    fn matches(&self, command: &CommandLine) -> bool {
+        if self.program != command.program || self.args != command.args {
+            return false;
+        }
+
+        match (&self.stdin, &command.stdin) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(expected), Some(actual)) => {
+                match (
+                    serde_json::from_str::<Value>(expected),
+                    serde_json::from_str::<Value>(actual),
+                ) {
+                    (Ok(expected), Ok(actual)) => json_matches(&expected, &actual),
+                    _ => expected == MASK || expected == actual,
+                }
+            }
+        }
+    }
+}
+
+/// Equality where a masked string in `expected` stands for any value.
+fn json_matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::String(mask), _) if mask == MASK => true,
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().all(|(key, value)| {
+                    actual
+                        .get(key)
+                        .is_some_and(|actual| json_matches(value, actual))
+                })
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual)
+                    .all(|(expected, actual)| json_matches(expected, actual))
+        }
+        _ => expected == actual,
+    }
+}
+
+/// `text` with the values of `SECRET_FIELDS` masked, or all of it if it
+/// isn't JSON and `mask_all` is set. Left as it was when there is nothing
+/// to mask.
+fn redact(text: &str, mask_all: bool) -> String {
+    fn mask_fields(value: &mut Value) -> bool {
+        let mut masked = false;
+        match value {
+            Value::Object(fields) => {
+                for (key, value) in fields.iter_mut() {
+                    if SECRET_FIELDS.contains(&key.as_str()) {
+                        *value = Value::String(MASK.to_string());
+                        masked = true;
+                    } else {
+                        masked |= mask_fields(value);
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for value in values.iter_mut() {
+                    masked |= mask_fields(value);
+                }
+            }
+            _ => {}
+        }
+        masked
+    }
+
+    let Ok(mut value) = serde_json::from_str::<Value>(text) else {
+        return if mask_all {
+            MASK.to_string()
+        } else {
+            text.to_string()
+        };
+    };
+
+    if mask_fields(&mut value) {
+        serde_json::to_string_pretty(&value).unwrap_or_else(|_| MASK.to_string())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Answers commands from a fixture file, or from expectations set up in
+/// code, instead of running them. Each invocation is used once, in the order
+/// they match.
+pub struct FakeRunner {
+    expected: Mutex<Vec<Invocation>>,
+}
+
+impl FakeRunner {
+    /// A runner expecting no commands yet, see `expect`.
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self {
+            expected: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Answers a command like `command`, including its stdin if it has one,
+    /// with `stdout` and exit code 0.
+    #[cfg(test)]
+    pub fn expect(self, command: CommandLine, stdout: &str) -> Self {
+        self.expect_output(command, 0, stdout, "")
+    }
+
+    /// Answers a command like `command`, including its stdin if it has one,
+    /// with `stderr` and a non-zero exit code.
+    #[cfg(test)]
+    pub fn expect_failure(self, command: CommandLine, stderr: &str) -> Self {
+        self.expect_output(command, 1, "", stderr)
+    }
+
+    #[cfg(test)]
+    fn expect_output(
+        self,
+        command: CommandLine,
+        exit_code: i32,
+        stdout: &str,
+        stderr: &str,
+    ) -> Self {
+        if let Ok(mut expected) = self.expected.lock() {
+            expected.push(Invocation {
+                program: command.program,
+                args: command.args,
+                stdin: command.stdin,
+                exit_code,
+                stdout: stdout.to_string(),
+                stderr: stderr.to_string(),
+            });
+        }
+        self
+    }
+
+    /// Fails with the expected commands that never ran.
+    pub fn verify(&self) -> CustomResult<()> {
+        let expected = self
+            .expected
+            .lock()
+            .map_err(|_| CustomError::CommandExecution("Fixture state is poisoned".to_string()))?;
+
+        if expected.is_empty() {
+            return Ok(());
+        }
+
+        let unused: Vec<String> = expected
+            .iter()
+            .map(|invocation| format!("{} {}", invocation.program, invocation.args.join(" ")))
+            .collect();
+        Err(CustomError::CommandExecution(format!(
+            "Expected commands never ran: {}",
+            unused.join(", ")
+        )))
+    }
+
+    pub fn from_fixture(path: &Path) -> CustomResult<Self> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            CustomError::CommandExecution(format!(
+                "Failed to read fixture {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        let expected = serde_json::from_str(&contents).map_err(|err| {
+            CustomError::Parse(format!(
+                "Failed to parse fixture {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        Ok(Self {
+            expected: Mutex::new(expected),
+        })
+    }
+}
+
+/// Catches tests that forget to call `verify`. Skipped while panicking, the
+/// failure that caused the panic is the one worth reading.
+impl Drop for FakeRunner {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            return;
+        }
+
+        if let Err(err) = self.verify() {
+            if cfg!(test) {
+                panic!("{}", err);
+            }
+            Logger::new().warn(err.to_string());
+        }
+    }
+}
+
+impl CommandRunner for FakeRunner {
+    fn run(&self, command: &CommandLine) -> CustomResult<CommandOutput> {
+        let mut expected = self
+            .expected
+            .lock()
+            .map_err(|_| CustomError::CommandExecution("Fixture state is poisoned".to_string()))?;
+
+        let index = expected
+            .iter()
+            .position(|invocation| invocation.matches(command))
+            .ok_or_else(|| {
+                CustomError::CommandExecution(format!("Unexpected command: {}", command))
+            })?;
+        let invocation = expected.remove(index);
+
+        Ok(CommandOutput {
+            exit_code: invocation.exit_code,
+            stdout: invocation.stdout.into_bytes(),
+            stderr: invocation.stderr.into_bytes(),
+        })
+    }
+}
+
+/// Runs commands for real and appends each of them with its result to a
+/// fixture file that `FakeRunner` can replay. Stdin that isn't JSON is
+/// masked as a whole, it may be a secret of its own.
+pub struct RecordingRunner {
+    inner: ProcessRunner,
+    path: PathBuf,
+    recorded: Mutex<Vec<Invocation>>,
+}
+
+impl RecordingRunner {
+    pub fn new(path: PathBuf) -> Self {
+        Logger::new().warn(format!(
+            "Recording commands to {}, known credential fields are masked but review it before sharing",
+            path.display()
+        ));
+
+        Self {
+            inner: ProcessRunner::new(),
+            path,
+            recorded: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl CommandRunner for RecordingRunner {
+    fn run(&self, command: &CommandLine) -> CustomResult<CommandOutput> {
+        let output = self.inner.run(command)?;
+
+        let mut recorded = self.recorded.lock().map_err(|_| {
+            CustomError::CommandExecution("Recording state is poisoned".to_string())
+        })?;
+        recorded.push(Invocation {
+            program: command.program.clone(),
+            args: command.args.clone(),
+            stdin: command.stdin.as_deref().map(|stdin| redact(stdin, true)),
+            exit_code: output.exit_code,
+            stdout: redact(&String::from_utf8_lossy(&output.stdout), false),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+
+        let json = serde_json::to_string_pretty(&*recorded).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to encode fixture: {}", err))
+        })?;
+        file_utils::write_private_atomic(&self.path, &json)?;
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role_credentials(role: &str, token: &str) -> CommandLine {
+        CommandLine::new("aws")
+            .args([
+                "sso",
+                "get-role-credentials",
+                "--cli-input-json",
+                "file:///dev/stdin",
+            ])
+            .stdin(format!(
+                r#"{{"roleName": "{}", "accessToken": "{}"}}"#,
+                role, token
+            ))
+    }
+
+    #[test]
+    fn matches_stdin_as_json() {
+        let runner = FakeRunner::new().expect(role_credentials("dev", MASK), "{}");
+
+        assert!(runner.run(&role_credentials("ops", "token")).is_err());
+        assert!(runner
+            .run(&role_credentials("dev", "token").stdin(
+                r#"{ "accessToken": "token",
+                     "roleName": "dev" }"#
+            ))
+            .is_ok());
+        runner.verify().unwrap();
+    }
+
+    #[test]
+    fn masks_credentials_when_recording() {
+        let dir = std::env::temp_dir().join(format!("raw-fixture-test-{}", std::process::id()));
+        let path = dir.join("fixture.json");
+        let _ = fs::remove_dir_all(&dir);
+
+        let command = CommandLine::new("sh")
+            .args([
+                "-c",
+                r#"cat >/dev/null; printf '{"roleCredentials": {"accessKeyId": "AKIA", "secretAccessKey": "secret", "sessionToken": "session"}}'"#,
+            ])
+            .stdin(r#"{"roleName": "dev", "accessToken": "token"}"#);
+        RecordingRunner::new(path.clone()).run(&command).unwrap();
+
+        let recorded: Vec<Invocation> =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let stdin: Value = serde_json::from_str(recorded[0].stdin.as_deref().unwrap()).unwrap();
+        let stdout: Value = serde_json::from_str(&recorded[0].stdout).unwrap();
+        assert_eq!(stdin["accessToken"], MASK);
+        assert_eq!(stdin["roleName"], "dev");
+        assert_eq!(stdout["roleCredentials"]["secretAccessKey"], MASK);
+        assert_eq!(stdout["roleCredentials"]["sessionToken"], MASK);
+        assert_eq!(stdout["roleCredentials"]["accessKeyId"], "AKIA");
+
+        // The masked token matches whatever token the replay is given
+        let replay = FakeRunner::from_fixture(&path).unwrap();
+        assert!(replay
+            .run(&command.stdin(r#"{"roleName": "dev", "accessToken": "other"}"#))
+            .is_ok());
+        replay.verify().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,161 @@
+mod dry_run;
+mod fixture;
+mod process;
+mod retry;
+
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    custom_error::{CustomError, CustomResult},
+    logger::Logger,
+};
+
+pub use dry_run::DryRunRunner;
+pub use fixture::{FakeRunner, RecordingRunner};
+pub use process::{foreground_running, kill_running, run_foreground, ProcessRunner};
+pub use retry::RetryPolicy;
+
+/// A program and its arguments, run directly without a shell so arguments
+/// are never interpreted. Secrets go through `stdin`, which is neither
+/// visible in the process list nor printed.
+#[derive(Debug, Clone)]
+pub struct CommandLine {
+    program: String,
+    args: Vec<String>,
+    stdin: Option<String>,
+    timeout: Option<Duration>,
+    interactive: bool,
+}
+
+impl CommandLine {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+            stdin: None,
+            timeout: None,
+            interactive: false,
+        }
+    }
+
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Written to the standard input of the child.
+    pub fn stdin<S: Into<String>>(mut self, input: S) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    /// How long the command may run before it's killed, when the default
+    /// of the runner is too short, e.g. while waiting for a browser login.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// For commands that may prompt on the terminal, like `git push` asking
+    /// for credentials. They keep the terminal and the process group of this
+    /// tool, so Ctrl-C reaches them directly.
+    pub fn interactive(mut self) -> Self {
+        self.interactive = true;
+        self
+    }
+}
+
+/// Shell-like rendering for logs, with stdin masked.
+impl Display for CommandLine {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+            if plain {
+                write!(f, " {}", arg)?;
+            } else {
+                write!(f, " '{}'", arg.replace('\'', "'\\''"))?;
+            }
+        }
+        if self.stdin.is_some() {
+            write!(f, " <<< ***")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a finished command printed and how it exited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOutput {
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Runs `CommandLine`s. Every type that shells out holds one, so a fake can
+/// stand in for the real AWS CLI and git.
+pub trait CommandRunner: Send + Sync {
+    /// Runs `command` whatever its exit code.
+    fn run(&self, command: &CommandLine) -> CustomResult<CommandOutput>;
+
+    /// Runs `command` and fails with its stderr when it exits non-zero.
+    fn execute(&self, command: &CommandLine) -> CustomResult<CommandOutput> {
+        let logger = Logger::new();
+        logger.debug(format!("Running: {}", command));
+
+        let output = self.run(command)?;
+
+        if output.exit_code != 0 {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            logger.error(format!("Failed to execute command: {}", command));
+            logger.error(format!("Error: {}", stderr));
+
+            // Keep stderr so callers can tell e.g. expired tokens from other failures
+            return Err(CustomError::from_stderr(&stderr));
+        }
+
+        Ok(output)
+    }
+}
+
+static DEFAULT_RUNNER: OnceLock<Arc<dyn CommandRunner>> = OnceLock::new();
+
+/// Replaces the runner used by `new()` constructors. Only the first call,
+/// made before any command runs, has an effect.
+pub fn set_default_runner(runner: Arc<dyn CommandRunner>) {
+    if DEFAULT_RUNNER.set(runner).is_err() {
+        Logger::new().warn("Command runner already initialised, keeping it");
+    }
+}
+
+/// The runner set with `set_default_runner`, or one running real processes.
+pub fn default_runner() -> Arc<dyn CommandRunner> {
+    Arc::clone(DEFAULT_RUNNER.get_or_init(|| Arc::new(ProcessRunner::new())))
+}
+
+/// Runs `command` on the blocking thread pool of tokio, so async callers
+/// don't stall the runtime while the child runs.
+pub async fn execute_async(
+    runner: Arc<dyn CommandRunner>,
+    command: CommandLine,
+) -> CustomResult<CommandOutput> {
+    tokio::task::spawn_blocking(move || runner.execute(&command))
+        .await
+        .map_err(|err| CustomError::CommandExecution(format!("Command task failed: {}", err)))?
+}
@@ -0,0 +1,254 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::custom_error::{CustomError, CustomResult};
+
+use super::{CommandLine, CommandOutput, CommandRunner};
+
+/// How long a command may run unless it sets its own `timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Children started by `ProcessRunner` that haven't exited yet.
+static RUNNING: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+
+/// Runs `CommandLine`s as child processes.
+pub struct ProcessRunner;
+
+impl ProcessRunner {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// `None` when the child is still running after `timeout`.
+    fn wait(child: &mut Child, timeout: Duration) -> CustomResult<Option<ExitStatus>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let exited = child
+                .try_wait()
+                .map_err(|err| CustomError::CommandExecution(err.to_string()))?;
+            match exited {
+                Some(status) => return Ok(Some(status)),
+                None if Instant::now() >= deadline => return Ok(None),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+impl CommandRunner for ProcessRunner {
+    fn run(&self, command: &CommandLine) -> CustomResult<CommandOutput> {
+        let mut process = Command::new(&command.program);
+        process
+            .args(&command.args)
+            .stdin(if command.stdin.is_some() {
+                Stdio::piped()
+            } else if command.interactive {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // A process group of its own, so Ctrl-C and timeouts kill whatever
+        // the child started as well, e.g. the Python process behind `aws`
+        #[cfg(unix)]
+        if !command.interactive {
+            use std::os::unix::process::CommandExt;
+            process.process_group(0);
+        }
+
+        let mut child = process.spawn().map_err(|err| {
+            let message = format!("Failed to start '{}': {}", command.program, err);
+            if err.kind() == std::io::ErrorKind::NotFound {
+                CustomError::ToolMissing(message)
+            } else {
+                CustomError::CommandExecution(message)
+            }
+        })?;
+        let running = Tracked::new(&child, !command.interactive);
+
+        // Read while the child runs, it blocks once a pipe buffer is full
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+
+        // Written while the child runs too, so one that never reads its
+        // stdin still times out instead of blocking the write
+        let write = write_all(child.stdin.take(), command.stdin.clone());
+
+        let timeout = command.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let Some(status) = Self::wait(&mut child, timeout)? else {
+            running.0.kill();
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CustomError::TimedOut(format!(
+                "Command timed out after {}s: {}",
+                timeout.as_secs(),
+                command
+            )));
+        };
+
+        // A child failing before it reads stdin closes the pipe, its exit
+        // status and stderr tell why, so the write error only counts when
+        // it succeeded
+        if let Ok(Err(err)) = write.join() {
+            if status.success() {
+                return Err(CustomError::CommandExecution(format!(
+                    "Failed to write to stdin of '{}': {}",
+                    command.program, err
+                )));
+            }
+        }
+
+        Ok(CommandOutput {
+            // Killed by a signal when there is no code
+            exit_code: status.code().unwrap_or(-1),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+fn write_all<W: Write + Send + 'static>(
+    pipe: Option<W>,
+    input: Option<String>,
+) -> JoinHandle<std::io::Result<()>> {
+    thread::spawn(move || match (pipe, input) {
+        // Dropping the pipe once written closes it, ending the input
+        (Some(mut pipe), Some(input)) => pipe.write_all(input.as_bytes()),
+        _ => Ok(()),
+    })
+}
+
+fn read_to_end<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Runs `process` sharing the terminal with this tool, like an interactive
+/// `CommandLine`, for children that need more than a `CommandLine` holds,
+/// e.g. an environment of their own.
+pub fn run_foreground(process: &mut Command) -> std::io::Result<ExitStatus> {
+    let mut child = process.spawn()?;
+    let _running = Tracked::new(&child, false);
+    child.wait()
+}
+
+/// Whether a child sharing the terminal is running. Ctrl-C reaches it as
+/// well, so it decides whether to exit, not this tool.
+pub fn foreground_running() -> bool {
+    RUNNING
+        .lock()
+        .map(|running| running.iter().any(|child| !child.own_group))
+        .unwrap_or(false)
+}
+
+/// Kills the children still running and everything they started, for when
+/// this tool is interrupted.
+pub fn kill_running() {
+    if let Ok(running) = RUNNING.lock() {
+        for child in running.iter() {
+            child.kill();
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Running {
+    pid: u32,
+    /// Whether the child leads a process group of its own
+    own_group: bool,
+}
+
+impl Running {
+    fn kill(&self) {
+        #[cfg(unix)]
+        {
+            // A negative pid signals the whole process group
+            let pid = self.pid as libc::pid_t;
+            let target = if self.own_group { -pid } else { pid };
+            unsafe {
+                libc::kill(target, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// Keeps a child in `RUNNING` for as long as it lives.
+struct Tracked(Running);
+
+impl Tracked {
+    fn new(child: &Child, own_group: bool) -> Self {
+        let running = Running {
+            pid: child.id(),
+            own_group,
+        };
+        if let Ok(mut all) = RUNNING.lock() {
+            all.push(running);
+        }
+        Self(running)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Ok(mut all) = RUNNING.lock() {
+            all.retain(|running| *running != self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_stderr_of_child_exiting_before_reading_stdin() {
+        let command = CommandLine::new("sh")
+            .args(["-c", "echo 'An error occurred (AccessDenied)' >&2; exit 3"])
+            .stdin("x".repeat(1 << 20));
+
+        let output = ProcessRunner::new().run(&command).unwrap();
+
+        assert_eq!(output.exit_code, 3);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr).trim(),
+            "An error occurred (AccessDenied)"
+        );
+    }
+
+    #[test]
+    fn kills_command_after_timeout() {
+        let command = CommandLine::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100));
+
+        let result = ProcessRunner::new().run(&command);
+
+        assert!(matches!(result, Err(CustomError::TimedOut(_))));
+    }
+
+    #[test]
+    fn times_out_child_not_reading_stdin() {
+        // More than a pipe buffer, the write would block until the child exits
+        let command = CommandLine::new("sleep")
+            .arg("5")
+            .stdin("x".repeat(1 << 20))
+            .timeout(Duration::from_millis(100));
+
+        let started = Instant::now();
+        let result = ProcessRunner::new().run(&command);
+
+        assert!(matches!(result, Err(CustomError::TimedOut(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    custom_error::{CustomError, CustomResult},
+    logger::Logger,
+};
+
+/// Retries a step only when it failed for a reason that can go away by
+/// itself: throttling, a network problem or a server error on the AWS side.
+/// Anything else, e.g. access denied or an invalid argument, fails at once.
+pub struct RetryPolicy {
+    logger: Logger,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    retry_unavailable: bool,
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self {
+            logger: Logger::new(),
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(8),
+            retry_unavailable: true,
+        }
+    }
+
+    /// For calls that aren't safe to repeat, like creating a pull request.
+    /// A network or server error may come after AWS applied the call, so
+    /// only throttled calls, which AWS rejects up front, are retried.
+    pub fn throttling_only() -> Self {
+        Self {
+            retry_unavailable: false,
+            ..Self::new()
+        }
+    }
+
+    pub fn run<T, F>(&self, step: F) -> CustomResult<T>
+    where
+        F: Fn() -> CustomResult<T>,
+    {
+        let mut attempt = 0;
+        loop {
+            match step() {
+                Err(err) if self.should_retry(&err) && attempt < self.max_retries => {
+                    attempt += 1;
+                    let delay = self.delay(attempt);
+                    self.logger.warn(format!(
+                        "{}; retry attempt {}/{} after {:.1}s delay",
+                        err,
+                        attempt,
+                        self.max_retries,
+                        delay.as_secs_f64()
+                    ));
+                    thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// `run` for async steps, sleeping without blocking the runtime.
+    pub async fn run_async<T, F, Fut>(&self, step: F) -> CustomResult<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = CustomResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match step().await {
+                Err(err) if self.should_retry(&err) && attempt < self.max_retries => {
+                    attempt += 1;
+                    let delay = self.delay(attempt);
+                    self.logger.warn(format!(
+                        "{}; retry attempt {}/{} after {:.1}s delay",
+                        err,
+                        attempt,
+                        self.max_retries,
+                        delay.as_secs_f64()
+                    ));
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn should_retry(&self, err: &CustomError) -> bool {
+        match err {
+            CustomError::Throttling(_) => true,
+            CustomError::Unavailable(_) => self.retry_unavailable,
+            _ => false,
+        }
+    }
+
+    /// Exponential backoff, of which a random half is dropped so calls
+    /// throttled at the same time don't all retry at the same time.
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        backoff.mul_f64(0.5 + jitter() / 2.0)
+    }
+}
+
+/// Random number in `[0, 1)`, seeded per call by the standard library.
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// `policy` without the waiting between attempts.
+    fn fast(policy: RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            ..policy
+        }
+    }
+
+    /// Attempts made by `policy` for a step that always fails with `error`.
+    fn attempts(policy: RetryPolicy, error: fn(String) -> CustomError) -> u32 {
+        let attempts = Cell::new(0);
+        let result: CustomResult<()> = policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            Err(error("failed".to_string()))
+        });
+
+        assert!(result.is_err());
+        attempts.get()
+    }
+
+    #[test]
+    fn retries_throttling_up_to_max_retries() {
+        assert_eq!(
+            attempts(fast(RetryPolicy::new()), CustomError::Throttling),
+            4
+        );
+        assert_eq!(
+            attempts(
+                fast(RetryPolicy::throttling_only()),
+                CustomError::Throttling
+            ),
+            4
+        );
+    }
+
+    #[test]
+    fn fails_at_once_when_access_denied() {
+        assert_eq!(
+            attempts(fast(RetryPolicy::new()), CustomError::AccessDenied),
+            1
+        );
+    }
+
+    #[test]
+    fn retries_unavailable_unless_throttling_only() {
+        assert_eq!(
+            attempts(fast(RetryPolicy::new()), CustomError::Unavailable),
+            4
+        );
+        assert_eq!(
+            attempts(
+                fast(RetryPolicy::throttling_only()),
+                CustomError::Unavailable
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn returns_first_success() {
+        let attempts = Cell::new(0);
+        let result = fast(RetryPolicy::new()).run(|| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(CustomError::Throttling("slow down".to_string()))
+            } else {
+                Ok(attempts.get())
+            }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn caps_delay_at_max_delay() {
+        let policy = RetryPolicy::new();
+
+        for attempt in 1..=40 {
+            let delay = policy.delay(attempt);
+            assert!(delay <= policy.max_delay, "{:?} at {}", delay, attempt);
+            assert!(delay >= policy.base_delay / 2);
+        }
+        assert!(policy.delay(10) >= policy.max_delay / 2);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -11,6 +12,49 @@ const CONFIG_FILE_NAME: &str = ".rust-aws-wrapper.toml";
 pub struct AppConfig {
     #[serde(default)]
     pub auth: AuthConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub pr: PrConfig,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct PrConfig {
+    /// Approval rules that `create-pr --approval-rule <name>` can attach,
+    /// e.g. `[pr.approval_rules.backend]`.
+    #[serde(default)]
+    pub approval_rules: HashMap<String, ApprovalRuleConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApprovalRuleConfig {
+    /// Number of approvals needed from the pool
+    pub approvals: u32,
+    /// ARNs allowed to approve, wildcards allowed; anyone when empty
+    #[serde(default)]
+    pub pool: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct StorageConfig {
+    /// Where cached credentials and stored secrets are kept. SSO access
+    /// tokens aren't: `aws sso login` writes them to ~/.aws/sso/cache itself,
+    /// and the AWS CLI only reads them from there.
+    #[serde(default)]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageBackend {
+    /// Plain files readable only by the current user
+    #[default]
+    File,
+    /// OS keyring (Secret Service, macOS Keychain, Windows Credential Manager),
+    /// falling back to `encrypted-file` when no keyring is reachable
+    Keyring,
+    /// Files encrypted with a passphrase from `RAW_STORAGE_PASSPHRASE` or a prompt
+    EncryptedFile,
 }
 
 #[derive(Debug, Deserialize)]
@@ -19,11 +63,23 @@ pub struct AuthConfig {
     /// (from environment variables or ~/.aws/credentials) are used instead.
     #[serde(default)]
     pub disable_sso: bool,
+
+    /// A warning is logged before AWS calls once the credentials set by this
+    /// tool expire within this many minutes.
+    #[serde(default = "default_expiry_warning_minutes")]
+    pub expiry_warning_minutes: i64,
+}
+
+fn default_expiry_warning_minutes() -> i64 {
+    10
 }
 
 impl Default for AuthConfig {
     fn default() -> Self {
-        Self { disable_sso: false }
+        Self {
+            disable_sso: false,
+            expiry_warning_minutes: default_expiry_warning_minutes(),
+        }
     }
 }
 
@@ -66,7 +122,7 @@ impl AppConfig {
 
     /// Searches for the config file starting from the current directory
     /// and walking up to parent directories.
-    fn find_config_file() -> Option<PathBuf> {
+    pub fn find_config_file() -> Option<PathBuf> {
         let mut dir = std::env::current_dir().ok()?;
 
         loop {
@@ -1,3 +1,5 @@
 pub const DEV_ROLE: &str = "conform5-edetek-dev-01.conform5-batch-dev";
 pub const DOMAIN_OWNER: &str = "022587608743";
 pub const SSO_START_URL: &str = "https://edetek.awsapps.com/start/#/";
+pub const SSO_REGION: &str = "us-east-1";
+pub const SSO_SESSION: &str = "sso";
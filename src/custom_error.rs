@@ -1,14 +1,239 @@
 pub type CustomResult<T> = core::result::Result<T, CustomError>;
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub enum CustomError {
+    /// An external command failed for a reason not covered below
     CommandExecution(String),
+    /// ~/.aws/config or the config file of this tool is missing or invalid
+    Config(String),
+    /// No SSO session, or the credentials expired or were rejected
+    Auth(String),
+    /// The credentials are valid but not allowed to do this
+    AccessDenied(String),
+    /// A repository, profile, pull request or other resource doesn't exist
+    NotFound(String),
+    /// AWS rejected the request because of its rate limits
+    Throttling(String),
+    /// A network problem or a server error on the AWS side
+    Unavailable(String),
+    /// Output of a command or contents of a file couldn't be understood
+    Parse(String),
+    /// The user declined a prompt or aborted the input
+    Cancelled(String),
+    /// A command didn't finish in time and was killed
+    TimedOut(String),
+    /// A program this tool runs, e.g. `aws` or `git`, isn't installed
+    ToolMissing(String),
+}
+
+/// AWS error codes, as printed by the CLI in `An error occurred (<Code>)`,
+/// for each error kind. Auth codes are matched exactly, as prefixes they
+/// would catch codes like `UnauthorizedOperation`, which is a denied
+/// action. The other kinds are matched as prefixes of the code.
+const AUTH_CODES: &[&str] = &[
+    "ExpiredToken",
+    "ExpiredTokenException",
+    "UnrecognizedClientException",
+    "InvalidClientTokenId",
+    "UnauthorizedException",
+    "InvalidGrantException",
+    "InvalidToken",
+    "InvalidTokenException",
+];
+const ACCESS_DENIED_CODES: &[&str] = &[
+    "AccessDenied",
+    "AuthorizationError",
+    "Forbidden",
+    "UnauthorizedOperation",
+];
+const THROTTLING_CODES: &[&str] = &[
+    "Throttling",
+    "TooManyRequests",
+    "RequestLimitExceeded",
+    "SlowDown",
+    "RequestThrottled",
+];
+const UNAVAILABLE_CODES: &[&str] = &[
+    "InternalFailure",
+    "InternalServerError",
+    "InternalError",
+    "ServiceUnavailable",
+    "ServiceFailure",
+    "RequestTimeout",
+    // Bare HTTP status codes, e.g. `(503)`
+    "5",
+];
+/// How botocore reports requests that never got a response.
+const NETWORK_ERRORS: &[&str] = &[
+    "Could not connect to the endpoint URL",
+    "Connect timeout on endpoint URL",
+    "Read timeout on endpoint URL",
+    "Connection was closed before we received a valid response",
+    "Connection reset by peer",
+    "Temporary failure in name resolution",
+];
+
+impl CustomError {
+    /// Classifies a failed command from its stderr.
+    pub fn from_stderr(stderr: &str) -> Self {
+        let message = format!("Failed to execute command: {}", stderr.trim());
+
+        let code = Self::aws_error_code(stderr).unwrap_or_default();
+        let has_code = |codes: &[&str]| codes.iter().any(|prefix| code.starts_with(prefix));
+
+        if AUTH_CODES.contains(&code)
+            || stderr.contains("Token has expired")
+            || stderr.contains("token included in the request is expired")
+            || stderr.contains("Error loading SSO Token")
+        {
+            Self::Auth(message)
+        } else if has_code(ACCESS_DENIED_CODES) {
+            Self::AccessDenied(message)
+        } else if has_code(THROTTLING_CODES) {
+            Self::Throttling(message)
+        } else if has_code(UNAVAILABLE_CODES)
+            || NETWORK_ERRORS.iter().any(|error| stderr.contains(error))
+        {
+            Self::Unavailable(message)
+        } else if code.contains("NotFound")
+            || code.contains("DoesNotExist")
+            || code.starts_with("NoSuch")
+            || code.ends_with("NotExist")
+        {
+            Self::NotFound(message)
+        } else {
+            Self::CommandExecution(message)
+        }
+    }
+
+    /// `AccessDeniedException` from
+    /// `An error occurred (AccessDeniedException) when calling ...`
+    pub fn aws_error_code(stderr: &str) -> Option<&str> {
+        let start = stderr.find("An error occurred (")? + "An error occurred (".len();
+        let end = stderr[start..].find(')')? + start;
+        Some(&stderr[start..end])
+    }
+
+    /// Process exit code, distinct per kind so scripts can react to them.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::CommandExecution(_) => 1,
+            Self::Config(_) => 3,
+            Self::Auth(_) => 4,
+            Self::AccessDenied(_) => 5,
+            Self::NotFound(_) => 6,
+            Self::Throttling(_) => 7,
+            Self::Parse(_) => 8,
+            Self::Unavailable(_) => 9,
+            Self::Cancelled(_) => 130,
+            Self::TimedOut(_) => 124,
+            Self::ToolMissing(_) => 127,
+        }
+    }
+
+    /// What the user can do about it.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::CommandExecution(_) | Self::Cancelled(_) => None,
+            Self::Config(_) => Some(
+                "Check ~/.aws/config and .rust-aws-wrapper.toml; `raw discover --write-config` can generate profiles",
+            ),
+            Self::Auth(_) => Some("Run `raw login` to start a new SSO session"),
+            Self::AccessDenied(_) => {
+                Some("The role lacks permission for this; use another profile or request access")
+            }
+            Self::NotFound(_) => Some("Check the repository, profile or ID for typos"),
+            Self::Throttling(_) => Some("AWS is rate limiting requests; wait a moment and retry"),
+            Self::Unavailable(_) => {
+                Some("Check your network connection or the AWS Health Dashboard, then retry")
+            }
+            Self::Parse(_) => Some(
+                "The output had an unexpected format; check `aws --version` is a current AWS CLI v2",
+            ),
+            Self::TimedOut(_) => Some("Check your network connection, then retry"),
+            Self::ToolMissing(_) => Some("Install it and make sure it is on your PATH"),
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Self::CommandExecution(message)
+            | Self::Config(message)
+            | Self::Auth(message)
+            | Self::AccessDenied(message)
+            | Self::NotFound(message)
+            | Self::Throttling(message)
+            | Self::Unavailable(message)
+            | Self::Parse(message)
+            | Self::Cancelled(message)
+            | Self::TimedOut(message)
+            | Self::ToolMissing(message) => message,
+        }
+    }
 }
 
 impl std::error::Error for CustomError {}
 impl core::fmt::Display for CustomError {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
-        write!(f, "{self:?}")
+        write!(f, "{}", self.message())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aws_error(code: &str) -> String {
+        format!(
+            "\nAn error occurred ({}) when calling the GetRepository operation: details\n",
+            code
+        )
+    }
+
+    #[test]
+    fn classifies_aws_error_codes() {
+        let cases = [
+            ("ExpiredTokenException", 4),
+            ("UnrecognizedClientException", 4),
+            ("InvalidClientTokenId", 4),
+            ("UnauthorizedException", 4),
+            ("AccessDeniedException", 5),
+            ("UnauthorizedOperation", 5),
+            ("ForbiddenException", 5),
+            ("ThrottlingException", 7),
+            ("TooManyRequestsException", 7),
+            ("ServiceUnavailableException", 9),
+            ("503", 9),
+            ("RepositoryDoesNotExistException", 6),
+            ("NoSuchEntity", 6),
+            ("InvalidParameterException", 1),
+        ];
+
+        for (code, exit_code) in cases {
+            let error = CustomError::from_stderr(&aws_error(code));
+            assert_eq!(error.exit_code(), exit_code, "{}: {:?}", code, error);
+        }
+    }
+
+    #[test]
+    fn classifies_errors_without_code() {
+        let cases = [
+            (
+                "Error when retrieving token from sso: Token has expired and refresh failed",
+                4,
+            ),
+            (
+                "Could not connect to the endpoint URL: \"https://codecommit.us-east-1.amazonaws.com/\"",
+                9,
+            ),
+            ("Connection reset by peer", 9),
+            ("fatal: not a git repository", 1),
+            ("", 1),
+        ];
+
+        for (stderr, exit_code) in cases {
+            let error = CustomError::from_stderr(stderr);
+            assert_eq!(error.exit_code(), exit_code, "{}: {:?}", stderr, error);
+        }
     }
 }
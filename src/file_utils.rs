@@ -0,0 +1,83 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::custom_error::{CustomError, CustomResult};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Turns every local write of this process, and the command of `raw exec`,
+/// into a printed notice, for `--dry-run` and `--replay`, where the commands
+/// only pretend to run.
+pub fn set_dry_run() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+/// Whether a write or other local side effect should be skipped, printing
+/// what it would have done.
+///
+/// `action` and `target` complete "would ...", e.g. `("write", path)`.
+pub fn skip_write(action: &str, target: impl Display) -> bool {
+    if !DRY_RUN.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    eprintln!("[dry-run] would {} {}", action, target);
+    true
+}
+
+/// Atomically replaces `path` with `contents`.
+///
+/// The data is written to a sibling temporary file readable only by the
+/// current user and then renamed over the target, so readers never observe a
+/// partially written file and secrets are never world-readable.
+pub fn write_private_atomic(path: &Path, contents: &str) -> CustomResult<()> {
+    write_private_atomic_bytes(path, contents.as_bytes())
+}
+
+/// Same as [`write_private_atomic`] for binary contents.
+pub fn write_private_atomic_bytes(path: &Path, contents: &[u8]) -> CustomResult<()> {
+    if skip_write("write", path.display()) {
+        return Ok(());
+    }
+
+    let dir = path.parent().ok_or_else(|| {
+        CustomError::CommandExecution(format!("Invalid file path: {}", path.display()))
+    })?;
+
+    fs::create_dir_all(dir).map_err(|err| {
+        CustomError::CommandExecution(format!(
+            "Failed to create directory {}: {}",
+            dir.display(),
+            err
+        ))
+    })?;
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = dir.join(format!(".{}.tmp-{}", file_name, std::process::id()));
+
+    let write = || -> std::io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    };
+
+    write().map_err(|err| {
+        let _ = fs::remove_file(&tmp_path);
+        CustomError::CommandExecution(format!("Failed to write {}: {}", path.display(), err))
+    })
+}
@@ -1,44 +1,77 @@
-use std::process::Output;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::{
+    command::{self, CommandLine, CommandRunner},
     custom_error::{CustomError, CustomResult},
-    zsh_command::ZshCommand,
 };
 
 pub struct Location {
-    zsh_command: ZshCommand,
+    runner: Arc<dyn CommandRunner>,
 }
 
 impl Location {
     pub fn new() -> Self {
-        Self {
-            zsh_command: ZshCommand::new(),
-        }
+        Self::with_runner(command::default_runner())
     }
 
-    pub fn get_repo_name(&self) -> CustomResult<String> {
-        let command = "git rev-parse --show-toplevel";
-        let output: Output = self.zsh_command.execute(command)?;
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self { runner }
+    }
 
-        let output_str = String::from_utf8(output.stdout).map_err(|err| {
-            CustomError::CommandExecution(format!("Failed to convert output to string: {}", err))
-        })?;
+    pub fn get_repo_name(&self) -> CustomResult<String> {
+        let output_str = self.get_toplevel()?;
 
         let result = self.get_name_from_output(&output_str)?;
 
         Ok(result)
     }
 
+    /// Root directory of the current git repository.
+    pub fn get_repo_root(&self) -> CustomResult<PathBuf> {
+        Ok(PathBuf::from(self.get_toplevel()?.trim()))
+    }
+
+    fn get_toplevel(&self) -> CustomResult<String> {
+        let command = CommandLine::new("git").args(["rev-parse", "--show-toplevel"]);
+        let output = self.runner.execute(&command)?;
+
+        String::from_utf8(output.stdout).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to convert output to string: {}", err))
+        })
+    }
+
     fn get_name_from_output(&self, output: &str) -> CustomResult<String> {
         let repo_name = output
             .trim()
             .split('/')
-            .last()
-            .ok_or_else(|| {
-                CustomError::CommandExecution("Failed to extract repository name".to_string())
-            })?
+            .next_back()
+            .ok_or_else(|| CustomError::Parse("Failed to extract repository name".to_string()))?
             .to_string();
 
         Ok(repo_name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::command::FakeRunner;
+
+    #[test]
+    fn replays_repository_of_fixture() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/location.json");
+        let runner = Arc::new(FakeRunner::from_fixture(&fixture).unwrap());
+        let location = Location::with_runner(runner.clone());
+
+        assert_eq!(location.get_repo_name().unwrap(), "payments-api");
+        assert_eq!(
+            location.get_repo_root().unwrap(),
+            PathBuf::from("/home/dev/src/payments-api")
+        );
+        assert!(location.get_repo_name().is_err());
+        runner.verify().unwrap();
+    }
+}
@@ -1,22 +1,65 @@
 mod aws;
 mod cli;
+mod command;
 pub mod config;
 mod constants;
 mod custom_error;
+mod file_utils;
 mod location;
 mod logger;
-mod zsh_command;
+mod pr_description;
+mod secret_store;
 
-use clap::Parser;
-use cli::{Cli, Commands};
+use std::sync::Arc;
+
+use aws::aws_pr::{ApprovalState, CommentLocation, CreatePrOptions, MergeCommit, MergeStrategy};
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cli::{Cli, Commands, PrCommands};
 use custom_error::CustomResult;
 use logger::Logger;
 
 #[tokio::main]
-async fn main() -> CustomResult<()> {
+async fn main() {
     Logger::init(logger::LogLevel::Info);
+
+    // Children mostly run in process groups of their own, which Ctrl-C
+    // in the terminal doesn't reach, so kill them before exiting. A child
+    // sharing the terminal gets the signal itself and may well handle it,
+    // so its exit is awaited and reported instead.
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if command::foreground_running() {
+                continue;
+            }
+            command::kill_running();
+            eprintln!("Interrupted");
+            std::process::exit(130);
+        }
+    });
+
+    if let Err(err) = run().await {
+        eprintln!("Error: {}", err);
+        if let Some(hint) = err.hint() {
+            eprintln!("Hint: {}", hint);
+        }
+        std::process::exit(err.exit_code());
+    }
+}
+
+async fn run() -> CustomResult<()> {
     let logger = Logger::new();
     let cli = Cli::parse();
+
+    if cli.dry_run {
+        file_utils::set_dry_run();
+        command::set_default_runner(Arc::new(command::DryRunRunner::new()));
+    } else if let Some(path) = cli.replay {
+        file_utils::set_dry_run();
+        command::set_default_runner(Arc::new(command::FakeRunner::from_fixture(&path)?));
+    } else if let Some(path) = cli.record {
+        command::set_default_runner(Arc::new(command::RecordingRunner::new(path)));
+    }
+
     let aws_cli = aws::aws_cli::AwsCli::new();
 
     match cli.command {
@@ -24,24 +67,116 @@ async fn main() -> CustomResult<()> {
             name,
             source,
             target,
+            description,
+            edit,
+            merge_strategy,
             abort,
+            approval_rule,
+            approval_rule_json,
         } => {
             let locator = location::Location::new();
             let repo_name = locator.get_repo_name()?;
             logger.info(format!("Repository name: {}", repo_name));
+            let options = CreatePrOptions {
+                title: name,
+                source_branch: source,
+                target,
+                merge_strategy,
+                description,
+                edit,
+                abort_early: abort,
+                approval_rule,
+                approval_rule_json,
+            };
             let result = aws_cli
-                .create_pull_request(
-                    repo_name.as_str(),
-                    name.as_deref(),
-                    source.as_deref(),
-                    &target,
-                    abort,
-                )
+                .create_pull_request(repo_name.as_str(), &options)
                 .await?;
             logger.info(format!("Pull request created successfully:\n{}", result));
         }
-        Commands::Login {} => {
-            aws_cli.login()?;
+        Commands::Pr { command } => match command {
+            PrCommands::List { status, mine, repo } => {
+                let repo_name = resolve_repo(repo)?;
+                aws_cli.list_pull_requests(&repo_name, status, mine)?;
+            }
+            PrCommands::Show { id, repo } => {
+                let repo_name = resolve_repo(repo)?;
+                aws_cli.show_pull_request(&repo_name, &id)?;
+            }
+            PrCommands::Merge {
+                id,
+                strategy,
+                message,
+                author_name,
+                email,
+                delete_branch,
+                repo,
+            } => {
+                // A fast-forward merge creates no commit to apply these to
+                if strategy == MergeStrategy::FastForward
+                    && (message.is_some() || author_name.is_some() || email.is_some())
+                {
+                    Cli::command()
+                        .error(
+                            ErrorKind::ArgumentConflict,
+                            "--message, --author-name and --email need --strategy squash or three-way",
+                        )
+                        .exit();
+                }
+
+                let repo_name = resolve_repo(repo)?;
+                let commit = MergeCommit {
+                    message,
+                    author_name,
+                    email,
+                };
+                aws_cli.merge_pull_request(&repo_name, &id, strategy, &commit, delete_branch)?;
+            }
+            PrCommands::Approve { id, repo } => {
+                let repo_name = resolve_repo(repo)?;
+                aws_cli.review_pull_request(&repo_name, id.as_deref(), ApprovalState::Approve)?;
+            }
+            PrCommands::Revoke { id, repo } => {
+                let repo_name = resolve_repo(repo)?;
+                aws_cli.review_pull_request(&repo_name, id.as_deref(), ApprovalState::Revoke)?;
+            }
+            PrCommands::Comment {
+                id,
+                message,
+                file,
+                line,
+                repo,
+            } => {
+                let repo_name = resolve_repo(repo)?;
+                let location = file.map(|file_path| CommentLocation { file_path, line });
+                aws_cli.comment_pull_request(
+                    &repo_name,
+                    id.as_deref(),
+                    &message,
+                    location.as_ref(),
+                )?;
+            }
+            PrCommands::Edit {
+                id,
+                title,
+                description,
+                edit,
+            } => {
+                aws_cli.edit_pull_request(&id, title.as_deref(), description.as_deref(), edit)?;
+            }
+            PrCommands::Close { id } => {
+                aws_cli.close_pull_request(&id)?;
+            }
+        },
+        Commands::Login {
+            write_credentials,
+            force,
+            assume_role,
+        } => {
+            aws_cli.login(
+                write_credentials.as_deref(),
+                force,
+                assume_role.into_options(),
+            )?;
             logger.info("Login successfully completed");
         }
         Commands::LoginNpm {} => {
@@ -56,19 +191,75 @@ async fn main() -> CustomResult<()> {
         }
         Commands::Morning {} => {
             logger.info("Good morning!");
-            aws_cli.login()?;
+            aws_cli.login(None, false, None)?;
             aws_cli.login_npm()?;
             logger.info("Have a great day!");
         }
-        Commands::Logs {} => {
+        Commands::Logs { discover } => {
             let aws_logs = aws::aws_logs::AwsLogs::new();
-            aws_logs.run()?;
+            aws_logs.run(discover)?;
         }
         Commands::LogsRaw {} => {
             let aws_logs = aws::aws_logs::AwsLogs::new();
             aws_logs.run_raw()?;
         }
+        Commands::Env {
+            profile,
+            format,
+            assume_role,
+        } => {
+            aws::aws_env::AwsEnv::new(profile)
+                .with_assume_role(assume_role.into_options())
+                .print(format)?;
+        }
+        Commands::Exec {
+            profile,
+            command,
+            assume_role,
+        } => {
+            let code = aws::aws_env::AwsEnv::new(profile)
+                .with_assume_role(assume_role.into_options())
+                .exec(&command)?;
+            std::process::exit(code);
+        }
+        Commands::Discover { write_config, all } => {
+            let discovery = aws::aws_discovery::AwsDiscovery::new();
+            if all {
+                let roles = discovery.all_roles()?;
+                discovery.write_profiles(&roles)?;
+            } else {
+                let role = discovery.select_role()?;
+                if write_config {
+                    discovery.write_profiles(&[role])?;
+                } else {
+                    print!("{}", role.profile_block(constants::SSO_REGION));
+                }
+            }
+        }
+        Commands::Status { profile } => {
+            aws::aws_status::AwsStatus::new().report(profile.as_deref())?;
+        }
+        Commands::Logout { npm, pip } => {
+            aws::aws_logout::AwsLogout::new().run(npm, pip)?;
+            logger.info("Logout successfully completed");
+        }
+        Commands::CredentialProcess {
+            profile,
+            assume_role,
+        } => {
+            aws::aws_env::AwsEnv::new(profile)
+                .with_assume_role(assume_role.into_options())
+                .print_credential_process()?;
+        }
     }
 
     Ok(())
 }
+
+/// Repository given with `--repo`, or the one of the current directory.
+fn resolve_repo(repo: Option<String>) -> CustomResult<String> {
+    match repo {
+        Some(repo) => Ok(repo),
+        None => location::Location::new().get_repo_name(),
+    }
+}
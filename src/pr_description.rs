@@ -0,0 +1,317 @@
+use std::fs;
+use std::sync::Arc;
+
+use dialoguer::Editor;
+
+use crate::{
+    command::{CommandLine, CommandRunner},
+    custom_error::{CustomError, CustomResult},
+    location::Location,
+    logger::Logger,
+};
+
+/// Template looked up relative to the repository root.
+const TEMPLATE_PATH: &str = ".raw/pull_request_template.md";
+
+/// Used when the repository has no template. Supports the placeholders
+/// `{{title}}`, `{{source}}`, `{{target}}` and `{{commits}}`.
+const DEFAULT_TEMPLATE: &str = "{{commits}}";
+
+/// Section headings for conventional-commit types, in display order.
+const COMMIT_GROUPS: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("docs", "Documentation"),
+    ("test", "Tests"),
+    ("build", "Build"),
+    ("ci", "CI"),
+    ("chore", "Chores"),
+];
+
+/// Builds pull request descriptions from the commits of the source branch.
+pub struct PrDescription {
+    logger: Logger,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl PrDescription {
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
+            logger: Logger::new(),
+            runner,
+        }
+    }
+
+    /// Fills the repository template, or the default one, with the commits
+    /// in `source` that are not in `target`.
+    pub fn build(&self, title: &str, source: &str, target: &str) -> CustomResult<String> {
+        let subjects = self.commit_subjects(source, target)?;
+        let template = self.read_template()?;
+
+        Ok(template
+            .replace("{{title}}", title)
+            .replace("{{source}}", source)
+            .replace("{{target}}", target)
+            .replace("{{commits}}", &Self::group_commits(&subjects))
+            .trim()
+            .to_string())
+    }
+
+    /// Opens `$EDITOR` on `description`; closing it without saving keeps the
+    /// description as it was.
+    pub fn edit(&self, description: &str) -> CustomResult<String> {
+        let edited = Editor::new()
+            .extension(".md")
+            .edit(description)
+            .map_err(|err| CustomError::CommandExecution(format!("Editor failed: {}", err)))?;
+
+        match edited {
+            Some(edited) => Ok(edited.trim().to_string()),
+            None => {
+                self.logger
+                    .warn("Description not saved, using it unchanged");
+                Ok(description.to_string())
+            }
+        }
+    }
+
+    fn commit_subjects(&self, source: &str, target: &str) -> CustomResult<Vec<String>> {
+        let Some(target_ref) = self.target_ref(target)? else {
+            self.logger.warn(format!(
+                "Branch '{}' not found locally or on a remote, leaving out the commits",
+                target
+            ));
+            return Ok(Vec::new());
+        };
+
+        let command = CommandLine::new("git").args([
+            "log",
+            "--no-merges",
+            "--reverse",
+            "--format=%s",
+            &format!("{}..{}", target_ref, source),
+            "--",
+        ]);
+        let output = self.runner.execute(&command)?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// The most up to date ref of `target`: the upstream of the local branch,
+    /// a remote-tracking branch of that name, or the local branch itself.
+    /// A local branch may be stale or missing altogether.
+    fn target_ref(&self, target: &str) -> CustomResult<Option<String>> {
+        let local = format!("refs/heads/{}", target);
+        let output = self.runner.execute(&CommandLine::new("git").args([
+            "for-each-ref",
+            "--format=%(refname)%09%(upstream:short)%09%(upstream:track)",
+            &local,
+            &format!("refs/remotes/*/{}", target),
+        ]))?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let refs: Vec<(&str, &str)> = output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let name = fields.next()?;
+                let upstream = fields.next().unwrap_or_default();
+                // Upstreams deleted on the remote are reported as gone
+                match fields.next() {
+                    Some("[gone]") => Some((name, "")),
+                    _ => Some((name, upstream)),
+                }
+            })
+            .collect();
+
+        let upstream = refs
+            .iter()
+            .find(|(name, upstream)| *name == local && !upstream.is_empty())
+            .map(|(_, upstream)| upstream.to_string());
+        let remote = refs
+            .iter()
+            .find_map(|(name, _)| name.strip_prefix("refs/remotes/"))
+            .map(str::to_string);
+        let local = refs
+            .iter()
+            .any(|(name, _)| *name == local)
+            .then(|| target.to_string());
+
+        Ok(upstream.or(remote).or(local))
+    }
+
+    fn read_template(&self) -> CustomResult<String> {
+        let path = Location::with_runner(Arc::clone(&self.runner))
+            .get_repo_root()?
+            .join(TEMPLATE_PATH);
+
+        match fs::read_to_string(&path) {
+            Ok(template) => {
+                self.logger
+                    .debug(format!("Using PR template {}", path.display()));
+                Ok(template)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Ok(DEFAULT_TEMPLATE.to_string())
+            }
+            Err(err) => Err(CustomError::CommandExecution(format!(
+                "Failed to read {}: {}",
+                path.display(),
+                err
+            ))),
+        }
+    }
+
+    /// Markdown sections per conventional-commit type; subjects without a
+    /// known type go under "Other Changes".
+    fn group_commits(subjects: &[String]) -> String {
+        let mut groups: Vec<(&str, Vec<&str>)> = COMMIT_GROUPS
+            .iter()
+            .map(|(_, heading)| (*heading, Vec::new()))
+            .collect();
+        let mut other = Vec::new();
+
+        for subject in subjects {
+            match Self::split_conventional(subject) {
+                Some((kind, summary)) => {
+                    match COMMIT_GROUPS.iter().position(|(prefix, _)| *prefix == kind) {
+                        Some(index) => groups[index].1.push(summary),
+                        None => other.push(subject.as_str()),
+                    }
+                }
+                None => other.push(subject.as_str()),
+            }
+        }
+        groups.push(("Other Changes", other));
+
+        groups
+            .into_iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(heading, entries)| {
+                let lines: Vec<String> =
+                    entries.iter().map(|entry| format!("- {}", entry)).collect();
+                format!("## {}\n\n{}", heading, lines.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// `feat(api)!: add x` -> `("feat", "add x")`
+    fn split_conventional(subject: &str) -> Option<(&str, &str)> {
+        let (prefix, summary) = subject.split_once(": ")?;
+        let kind = prefix
+            .trim_end_matches('!')
+            .split('(')
+            .next()
+            .unwrap_or_default();
+
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_lowercase()) {
+            return None;
+        }
+
+        Some((kind, summary.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::FakeRunner;
+
+    fn subjects(subjects: &[&str]) -> Vec<String> {
+        subjects.iter().map(|subject| subject.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_conventional_subjects() {
+        assert_eq!(
+            PrDescription::split_conventional("feat(api)!: drop v1"),
+            Some(("feat", "drop v1"))
+        );
+        assert_eq!(
+            PrDescription::split_conventional("fix: handle  empty list "),
+            Some(("fix", "handle  empty list"))
+        );
+        assert_eq!(PrDescription::split_conventional("Fix: typo"), None);
+        assert_eq!(PrDescription::split_conventional("Update README"), None);
+        assert_eq!(PrDescription::split_conventional(": no type"), None);
+    }
+
+    #[test]
+    fn groups_commits_in_display_order() {
+        let grouped = PrDescription::group_commits(&subjects(&[
+            "fix: handle timeouts",
+            "feat(api)!: drop v1",
+            "wip: half done",
+            "Update README",
+            "feat: add retries",
+        ]));
+
+        assert_eq!(
+            grouped,
+            "## Features\n\n- drop v1\n- add retries\n\n\
+             ## Bug Fixes\n\n- handle timeouts\n\n\
+             ## Other Changes\n\n- wip: half done\n- Update README"
+        );
+    }
+
+    #[test]
+    fn groups_no_commits_to_nothing() {
+        assert_eq!(PrDescription::group_commits(&[]), "");
+    }
+
+    fn target_ref(for_each_ref: &str) -> Option<String> {
+        let runner = Arc::new(FakeRunner::new().expect(
+            CommandLine::new("git").args([
+                "for-each-ref",
+                "--format=%(refname)%09%(upstream:short)%09%(upstream:track)",
+                "refs/heads/main",
+                "refs/remotes/*/main",
+            ]),
+            for_each_ref,
+        ));
+
+        let target_ref = PrDescription::with_runner(runner.clone())
+            .target_ref("main")
+            .unwrap();
+        runner.verify().unwrap();
+        target_ref
+    }
+
+    #[test]
+    fn prefers_upstream_of_local_branch() {
+        assert_eq!(
+            target_ref(
+                "refs/heads/main\tupstream/main\t[behind 2]\nrefs/remotes/origin/main\t\t\nrefs/remotes/upstream/main\t\t\n"
+            ),
+            Some("upstream/main".to_string())
+        );
+    }
+
+    #[test]
+    fn skips_gone_upstream() {
+        assert_eq!(
+            target_ref("refs/heads/main\torigin/main\t[gone]\nrefs/remotes/fork/main\t\t\n"),
+            Some("fork/main".to_string())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_remote_then_local_branch() {
+        assert_eq!(
+            target_ref("refs/remotes/origin/main\t\t\n"),
+            Some("origin/main".to_string())
+        );
+        assert_eq!(
+            target_ref("refs/heads/main\t\t\n"),
+            Some("main".to_string())
+        );
+        assert_eq!(target_ref(""), None);
+    }
+}
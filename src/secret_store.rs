@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use dialoguer::Password;
+
+use crate::{
+    config::{AppConfig, StorageBackend},
+    custom_error::{CustomError, CustomResult},
+    file_utils,
+    logger::Logger,
+};
+
+/// Keyring service name and directory under the user's cache dir.
+const APP_NAME: &str = "rust-aws-wrapper";
+const PASSPHRASE_ENV: &str = "RAW_STORAGE_PASSPHRASE";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Named secrets such as cached credentials, kept in the backend selected by
+/// `[storage] backend` in the config file.
+pub trait SecretStore {
+    fn get(&self, name: &str) -> CustomResult<Option<String>>;
+    fn set(&self, name: &str, value: &str) -> CustomResult<()>;
+    fn delete(&self, name: &str) -> CustomResult<()>;
+}
+
+/// Opens the store configured in `AppConfig`.
+pub fn open() -> CustomResult<Box<dyn SecretStore>> {
+    let dir = dirs::cache_dir()
+        .ok_or_else(|| CustomError::CommandExecution("Failed to get cache directory".to_string()))?
+        .join(APP_NAME);
+
+    match AppConfig::load().storage.backend {
+        StorageBackend::File => Ok(Box::new(FileStore { dir })),
+        StorageBackend::EncryptedFile => Ok(Box::new(EncryptedFileStore { dir })),
+        StorageBackend::Keyring => {
+            if KeyringStore::is_available() {
+                Ok(Box::new(KeyringStore))
+            } else {
+                Logger::new().warn("No OS keyring available, using encrypted file storage");
+                Ok(Box::new(EncryptedFileStore { dir }))
+            }
+        }
+    }
+}
+
+fn read_optional(path: &Path) -> CustomResult<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(CustomError::CommandExecution(format!(
+            "Failed to read {}: {}",
+            path.display(),
+            err
+        ))),
+    }
+}
+
+fn remove_optional(path: &Path) -> CustomResult<()> {
+    if file_utils::skip_write("remove", path.display()) {
+        return Ok(());
+    }
+
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(CustomError::CommandExecution(format!(
+            "Failed to remove {}: {}",
+            path.display(),
+            err
+        ))),
+    }
+}
+
+/// Plain JSON files readable only by the current user.
+struct FileStore {
+    dir: PathBuf,
+}
+
+impl SecretStore for FileStore {
+    fn get(&self, name: &str) -> CustomResult<Option<String>> {
+        let contents = read_optional(&self.dir.join(format!("{}.json", name)))?;
+        Ok(contents.map(|bytes| String::from_utf8_lossy(&bytes).to_string()))
+    }
+
+    fn set(&self, name: &str, value: &str) -> CustomResult<()> {
+        file_utils::write_private_atomic(&self.dir.join(format!("{}.json", name)), value)
+    }
+
+    fn delete(&self, name: &str) -> CustomResult<()> {
+        remove_optional(&self.dir.join(format!("{}.json", name)))
+    }
+}
+
+/// Entries of the OS keyring under the `rust-aws-wrapper` service.
+struct KeyringStore;
+
+impl KeyringStore {
+    /// A missing entry proves the keyring answered; anything else means there
+    /// is no usable keyring, e.g. no Secret Service on a headless machine.
+    fn is_available() -> bool {
+        match keyring::Entry::new(APP_NAME, "probe").and_then(|entry| entry.get_password()) {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(err) => {
+                Logger::new().debug(format!("OS keyring unavailable: {}", err));
+                false
+            }
+        }
+    }
+
+    fn entry(name: &str) -> CustomResult<keyring::Entry> {
+        keyring::Entry::new(APP_NAME, name).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to open keyring entry: {}", err))
+        })
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn get(&self, name: &str) -> CustomResult<Option<String>> {
+        match Self::entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(CustomError::CommandExecution(format!(
+                "Failed to read '{}' from keyring: {}",
+                name, err
+            ))),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> CustomResult<()> {
+        if file_utils::skip_write("write", format!("'{}' to the keyring", name)) {
+            return Ok(());
+        }
+
+        Self::entry(name)?.set_password(value).map_err(|err| {
+            CustomError::CommandExecution(format!("Failed to write '{}' to keyring: {}", name, err))
+        })
+    }
+
+    fn delete(&self, name: &str) -> CustomResult<()> {
+        if file_utils::skip_write("remove", format!("'{}' from the keyring", name)) {
+            return Ok(());
+        }
+
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(CustomError::CommandExecution(format!(
+                "Failed to delete '{}' from keyring: {}",
+                name, err
+            ))),
+        }
+    }
+}
+
+/// Files encrypted with ChaCha20-Poly1305 under a key derived with Argon2
+/// from a passphrase. Each file is `salt || nonce || ciphertext`.
+struct EncryptedFileStore {
+    dir: PathBuf,
+}
+
+impl EncryptedFileStore {
+    /// Asked once per process, unless provided through the environment.
+    fn passphrase() -> CustomResult<&'static str> {
+        static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+        if let Some(passphrase) = PASSPHRASE.get() {
+            return Ok(passphrase);
+        }
+
+        let passphrase = match std::env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) => passphrase,
+            Err(_) => Password::new()
+                .with_prompt("Passphrase for encrypted credential storage")
+                .interact()
+                .map_err(|err| CustomError::Cancelled(format!("Input failed: {}", err)))?,
+        };
+
+        Ok(PASSPHRASE.get_or_init(|| passphrase))
+    }
+
+    fn cipher(salt: &[u8]) -> CustomResult<ChaCha20Poly1305> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(Self::passphrase()?.as_bytes(), salt, &mut key)
+            .map_err(|err| {
+                CustomError::CommandExecution(format!("Failed to derive storage key: {}", err))
+            })?;
+
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.enc", name))
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, name: &str) -> CustomResult<Option<String>> {
+        let Some(contents) = read_optional(&self.path(name))? else {
+            return Ok(None);
+        };
+
+        if contents.len() < SALT_LEN + NONCE_LEN {
+            return Err(CustomError::Parse(format!(
+                "Encrypted storage entry '{}' is corrupt",
+                name
+            )));
+        }
+
+        let (salt, rest) = contents.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let plaintext = Self::cipher(salt)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                CustomError::Auth(format!("Failed to decrypt '{}', wrong passphrase?", name))
+            })?;
+
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|err| CustomError::Parse(format!("Failed to decode '{}': {}", name, err)))
+    }
+
+    fn set(&self, name: &str, value: &str) -> CustomResult<()> {
+        // Checked up front so a dry run doesn't ask for the passphrase
+        if file_utils::skip_write("write", self.path(name).display()) {
+            return Ok(());
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let ciphertext = Self::cipher(&salt)?
+            .encrypt(&nonce, value.as_bytes())
+            .map_err(|err| {
+                CustomError::CommandExecution(format!("Failed to encrypt '{}': {}", name, err))
+            })?;
+
+        let mut contents = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+        contents.extend_from_slice(&salt);
+        contents.extend_from_slice(&nonce);
+        contents.extend_from_slice(&ciphertext);
+
+        file_utils::write_private_atomic_bytes(&self.path(name), &contents)
+    }
+
+    fn delete(&self, name: &str) -> CustomResult<()> {
+        remove_optional(&self.path(name))
+    }
+}